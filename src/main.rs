@@ -3,54 +3,963 @@ use std::path::{Path, PathBuf};
 use pulldown_cmark::{html, Options, Parser};
 use regex::Regex;
 use katex::{Opts, OutputType};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
 
-#[derive(Debug, serde::Deserialize)]
+// How much the build prints: --quiet shows errors only, --verbose adds per-file lines
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LogLevel {
+    Quiet,
+    #[default]
+    Normal,
+    Verbose,
+}
+
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Normal as u8);
+
+fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+fn log_enabled(level: LogLevel) -> bool {
+    level as u8 <= LOG_LEVEL.load(Ordering::Relaxed)
+}
+
+// --dry-run: render everything but only report what would be written
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+// --profile: cumulative time spent in each build phase, printed after the summary
+#[derive(Debug, Clone, Copy)]
+enum Phase {
+    Math,
+    Markdown,
+    Links,
+    Assets,
+}
+
+const PHASE_NAMES: [&str; 4] = ["Math rendering", "Markdown parsing", "Internal links", "Asset copying"];
+
+static PROFILE: AtomicBool = AtomicBool::new(false);
+static PHASE_NANOS: [AtomicU64; 4] = [const { AtomicU64::new(0) }; 4];
+
+fn timed<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    if !PROFILE.load(Ordering::Relaxed) {
+        return f();
+    }
+    let started = std::time::Instant::now();
+    let value = f();
+    PHASE_NANOS[phase as usize].fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    value
+}
+
+// Summary output, hidden by --quiet
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        if log_enabled(LogLevel::Normal) {
+            println!($($arg)*);
+        }
+    };
+}
+
+// Warnings and written files are also collected for --report-format json
+static WARNINGS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+static OUTPUT_FILES: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+fn record(list: &std::sync::Mutex<Vec<String>>, entry: String) {
+    if let Ok(mut list) = list.lock() {
+        list.push(entry);
+    }
+}
+
+// Warnings go to stderr, hidden by --quiet
+macro_rules! log_warn {
+    ($($arg:tt)*) => {
+        let message = format!($($arg)*);
+        if log_enabled(LogLevel::Normal) {
+            eprintln!("Warning: {}", message);
+        }
+        record(&WARNINGS, message);
+    };
+}
+
+// Per-file detail, shown only with --verbose
+macro_rules! log_verbose {
+    ($($arg:tt)*) => {
+        if log_enabled(LogLevel::Verbose) {
+            println!($($arg)*);
+        }
+    };
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
 struct FrontMatter {
     title: Option<String>,
+    css: Option<Vec<String>>,      // Extra stylesheets for this page only
+    scripts: Option<Vec<PageScript>>,  // Extra scripts for this page only
+    author: Option<String>,
+    authors: Option<Vec<String>>,
+    tags: Option<Vec<String>>,
+    slug: Option<String>,  // Overrides the output filename, e.g. "annual-report" -> annual-report.html
+    aliases: Option<Vec<String>>,  // Old URLs that should redirect to this page
+    navbar: Option<bool>,  // Set to false to hide the site navbar on this page
+    canonical: Option<String>,  // Explicit canonical URL, e.g. for syndicated content
+    lang: Option<String>,  // Language of this page, e.g. "es"
+    date: Option<String>,  // Publication date, e.g. "2024-03-01"
+    weight: Option<i32>,  // Default sort position when there is no page_order, lower first
+    noindex: Option<bool>,  // Ask search engines to skip this page and leave it out of sitemap.xml
+    title_heading: Option<bool>,  // Overrides the title_heading config option for this page
+    template: Option<String>,  // Layout from assets/templates/<name>.html, e.g. "landing"
+    redirect_to: Option<String>,  // Publish only a redirect to this page or URL, keeping the navbar entry
+    cover: Option<String>,  // Full-width image shown above the title, e.g. "assets/covers/sir.jpg"
+    raw: Option<bool>,  // The body is an HTML fragment placed in the layout as-is, like a .html.md file
+    data: Option<serde_yaml::Value>,  // Values for {{ data.x }} and {% for x in data.list %} in the body
+    draft: Option<bool>,  // Leave the page out of the build (counted as skipped) until this is removed
 }
 
+// A `scripts` entry: a plain path, or {src: "...", module: true, defer: true}
 #[derive(Debug, serde::Deserialize)]
+#[serde(untagged)]
+enum PageScript {
+    Src(String),
+    Detailed {
+        src: String,
+        #[serde(default)]
+        module: bool,
+        #[serde(default)]
+        defer: bool,
+    },
+}
+
+impl PageScript {
+    fn to_tag(&self, asset_prefix: &str) -> String {
+        let (src, module, defer) = match self {
+            PageScript::Src(src) => (src, false, false),
+            PageScript::Detailed { src, module, defer } => (src, *module, *defer),
+        };
+        format!(
+            "<script{}{} src=\"{}\"></script>",
+            if module { " type=\"module\"" } else { "" },
+            if defer { " defer" } else { "" },
+            resolve_page_url(src, asset_prefix)
+        )
+    }
+}
+
+impl FrontMatter {
+    fn all_authors(&self) -> Vec<String> {
+        // Singular `author` comes first, followed by any `authors` not already listed
+        let mut names: Vec<String> = Vec::new();
+        for name in self.author.iter().chain(self.authors.iter().flatten()) {
+            let name = name.trim();
+            if !name.is_empty() && !names.iter().any(|n| n == name) {
+                names.push(name.to_string());
+            }
+        }
+        names
+    }
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
 struct Config {
     page_order: Option<Vec<serde_yaml::Value>>,
     navbar_order: Option<Vec<serde_yaml::Value>>,  // New: allows manual ordering including dropdowns
     dropdowns: Option<std::collections::HashMap<String, serde_yaml::Value>>,
+    search_index: Option<bool>,  // Write dist/search-index.json for client-side search
+    favicon: Option<String>,     // Defaults to assets/logo.png
+    logo: Option<String>,        // Navbar logo, defaults to assets/logo-wide.png
+    base_path: Option<String>,   // Deployment prefix when hosted under a subdirectory, e.g. "/mysite/"
+    absolute_links: Option<bool>,  // Fully qualify every internal link and asset URL with site_url
+    site_url: Option<String>,    // Public URL of the site root, e.g. "https://example.com/"
+    code_copy: Option<bool>,     // Add a "Copy" button to code blocks
+    page_images: Option<bool>,   // Resolve relative <img> paths from the page's directory
+    default_lang: Option<String>,  // <html lang> for pages without their own, defaults to "en"
+    reading_time: Option<bool>,  // Show an "X min read" estimate on each page
+    last_updated: Option<bool>,  // Show each page's last git commit date (file mtime if untracked)
+    minify: Option<bool>,        // Collapse whitespace and comments in generated HTML
+    precompress: Option<Vec<String>>,  // Also write .gz and/or .br next to each page: [gzip, brotli]
+    precompress_assets: Option<bool>,  // With precompress, compress text assets (CSS, JS, SVG, ...) too
+    highlight_theme: Option<String>,  // highlight.js theme name, or a stylesheet path/URL
+    icons: Option<bool>,         // Set to false to leave out FontAwesome entirely
+    navbar_exclude: Option<Vec<String>>,  // Pages or directories (globs allowed) built but left out of the navbar
+    graphviz: Option<String>,    // Path to the Graphviz `dot` binary for ```dot blocks, default "dot"
+    build_cache: Option<bool>,   // Reuse unchanged pages via dist/.ideeep-cache.json content hashes
+    line_numbers: Option<bool>,  // Number the lines of every code block; ```lang {linenos} does one block
+    extra_head: Option<String>,  // Raw HTML added before </head> on every page, e.g. analytics
+    body_class: Option<String>,  // Class attribute for <body> on every page
+    viewport: Option<String>,    // <meta name="viewport"> content, default "width=device-width, initial-scale=1.0"
+    theme_color: Option<String>,  // <meta name="theme-color"> for mobile browser chrome, e.g. "#000000"
+    icons_url: Option<String>,   // FontAwesome kit script, or a self-hosted .css such as assets/fontawesome/css/all.min.css
+    highlight_language_aliases: Option<std::collections::HashMap<String, String>>,  // e.g. sh: bash
+    data_dir: Option<String>,    // Where {% table "..." %} looks for CSV files, default the content root
+    content_dirs: Option<Vec<String>>,  // Content roots merged in order, later ones win; default ["content"]
+    optimize_css: Option<bool>,  // Load styles.css without blocking first paint
+    emoji: Option<bool>,         // Replace :shortcode: emoji, e.g. :rocket:
+    title_heading: Option<bool>,  // Show the page title as an <h1> above the content, default off
+    toc: Option<bool>,           // Table of contents of the page's headings above the content
+    toc_min: Option<u8>,         // Shallowest heading level in the table of contents, default 1
+    toc_depth: Option<u8>,       // Deepest heading level in the table of contents, default 3
+    site_name: Option<String>,   // Appended to every <title>; the home page's <title> is just this
+    title_separator: Option<String>,  // Between page title and site_name in <title>, default " · "
+    #[serde(default)]
+    markdown: MarkdownOptions,   // Toggle individual markdown extensions, all on by default
+    abbreviations: Option<bool>,  // Wrap terms defined by `*[HTML]: ...` lines in <abbr>
+    default_404: Option<bool>,   // Write a minimal 404.html when content/404.md doesn't exist
+    section_indexes: Option<bool>,  // Generate dir/index.html listings for directories without one
+    paginate: Option<usize>,     // Entries per generated listing page, continued at dir/page/2/index.html...
+    external_links_new_tab: Option<bool>,  // Open external content links in a new tab, default true
+    katex_macros: Option<std::collections::HashMap<String, String>>,  // e.g. "\\RR": "\\mathbb{R}"
+    math_preserve_newlines: Option<bool>,  // Keep line breaks inside math sources instead of joining lines
+    math_strict: Option<bool>,   // Fail the build on any KaTeX error instead of rendering it in red
+    math_delimiters: Option<Vec<String>>,  // Which of "$", "$$", "\\(", "\\[" mark math, default all four
+    #[serde(flatten)]
+    unknown: std::collections::BTreeMap<String, serde_yaml::Value>,  // Catches typos like `navbar_oder`
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct MarkdownOptions {
+    tables: Option<bool>,
+    footnotes: Option<bool>,
+    strikethrough: Option<bool>,
+    tasklists: Option<bool>,
+    smart_punctuation: Option<bool>,  // Curly quotes and dashes, e.g. -- becomes an en dash
+    heading_attributes: Option<bool>,  // `# Heading {#id .class}`
+    #[serde(flatten)]
+    unknown: std::collections::BTreeMap<String, serde_yaml::Value>,
+}
+
+impl MarkdownOptions {
+    fn parser_options(&self) -> Options {
+        // Start from everything pulldown-cmark offers and switch off what's disabled
+        let mut options = Options::all();
+        let toggles = [
+            (self.tables, Options::ENABLE_TABLES),
+            (self.footnotes, Options::ENABLE_FOOTNOTES),
+            (self.strikethrough, Options::ENABLE_STRIKETHROUGH),
+            (self.tasklists, Options::ENABLE_TASKLISTS),
+            (self.smart_punctuation, Options::ENABLE_SMART_PUNCTUATION),
+            (self.heading_attributes, Options::ENABLE_HEADING_ATTRIBUTES),
+        ];
+        for (enabled, flag) in toggles {
+            if enabled == Some(false) {
+                options.remove(flag);
+            }
+        }
+        options
+    }
+}
+
+fn normalize_base_path(base_path: &str) -> String {
+    // "mysite" -> "/mysite/", "https://example.com/mysite" -> "https://example.com/mysite/"
+    let trimmed = base_path.trim().trim_end_matches('/');
+    if trimmed.contains("://") || trimmed.starts_with('/') {
+        format!("{}/", trimmed)
+    } else {
+        format!("/{}/", trimmed).replace("//", "/")
+    }
+}
+
+fn link_base_path(config: &Config) -> Option<String> {
+    // Prefix for every internal URL: site_url itself in absolute_links mode, else base_path.
+    // None means links are relative to the page.
+    if config.absolute_links == Some(true) {
+        return config.site_url.as_deref().map(normalize_base_path);
+    }
+    config.base_path.as_deref().map(normalize_base_path)
+}
+
+fn site_root_path(config: &Config) -> String {
+    // Root-absolute prefix for pages a host serves at arbitrary URLs (404.html):
+    // base_path (or site_url with absolute_links) when set, otherwise the path of site_url,
+    // otherwise the domain root
+    if let Some(base) = link_base_path(config) {
+        return base;
+    }
+    if let Some(ref url) = config.site_url {
+        let after_scheme = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+        let path = after_scheme.find('/').map(|i| &after_scheme[i..]).unwrap_or("/");
+        return normalize_base_path(path);
+    }
+    "/".to_string()
+}
+
+fn resolve_site_image(configured: Option<&String>, default: &str) -> Option<String> {
+    // Use the configured path (or the default) only if it can actually be served
+    let path = configured.map(|s| s.as_str()).unwrap_or(default);
+    if is_external_url(path) || Path::new(path).exists() {
+        Some(path.to_string())
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+struct SearchEntry {
+    title: String,
+    url: String,
+    excerpt: String,
+    tags: Vec<String>,
+}
+
+// File extensions recognised as markdown sources
+const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown", "mdown", "mkd"];
+
+fn is_markdown_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .map(|ext| MARKDOWN_EXTENSIONS.contains(&ext))
+        .unwrap_or(false)
+}
+
+fn strip_markdown_extension(href: &str) -> Option<&str> {
+    // "notes/sir.markdown" -> Some("notes/sir"), "sir.html" -> None
+    MARKDOWN_EXTENSIONS.iter()
+        .find_map(|ext| href.strip_suffix(&format!(".{}", ext)))
+}
+
+fn is_raw_html_source(path: &Path) -> bool {
+    // "landing.html.md": a hand-written HTML fragment that still gets the site's navbar and footer
+    path.file_stem().and_then(|s| s.to_str()).is_some_and(|stem| stem.ends_with(".html"))
+}
+
+fn page_key(relative_path: &Path) -> String {
+    // "math/sir.md" -> "math/sir", with separators normalized
+    relative_path.with_extension("")
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+fn split_frontmatter<'a>(content: &'a str, fence: &str) -> Option<(&'a str, &'a str)> {
+    // Returns (front matter block, markdown body) for a block delimited by `fence` lines.
+    // The opening fence must be the very first line of the file.
+    let rest = content.strip_prefix(fence)?;
+    let rest = rest.strip_prefix('\n').or_else(|| rest.strip_prefix("\r\n"))?;
+    // The closing fence must be a whole line; it may also end the file
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end_matches(['\r', '\n']) == fence {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+fn toml_dates_to_strings(value: toml::Value) -> toml::Value {
+    // TOML has a native datetime type; front matter fields expect plain strings
+    match value {
+        toml::Value::Datetime(dt) => toml::Value::String(dt.to_string()),
+        toml::Value::Array(items) => toml::Value::Array(items.into_iter().map(toml_dates_to_strings).collect()),
+        toml::Value::Table(table) => toml::Value::Table(
+            table.into_iter().map(|(k, v)| (k, toml_dates_to_strings(v))).collect()
+        ),
+        other => other,
+    }
+}
+
+fn glob_match(pattern: &str, key: &str) -> bool {
+    // `*` matches within one path segment, `**` across segments, `?` a single character
+    fn matches(pattern: &[char], key: &[char]) -> bool {
+        match pattern.first() {
+            None => key.is_empty(),
+            Some('*') if pattern.get(1) == Some(&'*') => {
+                (0..=key.len()).any(|i| matches(&pattern[2..], &key[i..]))
+            }
+            Some('*') => {
+                let segment_len = key.iter().position(|&c| c == '/').unwrap_or(key.len());
+                (0..=segment_len).any(|i| matches(&pattern[1..], &key[i..]))
+            }
+            Some('?') => !key.is_empty() && key[0] != '/' && matches(&pattern[1..], &key[1..]),
+            Some(&c) => key.first() == Some(&c) && matches(&pattern[1..], &key[1..]),
+        }
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let key: Vec<char> = key.chars().collect();
+    matches(&pattern, &key)
+}
+
+// One line of .ideepignore
+struct IgnoreRule {
+    pattern: String,
+    anchored: bool,  // Contains a '/', so it matches from the content root
+    dir_only: bool,  // Trailing '/'
+    negated: bool,   // Leading '!' re-includes a path
+}
+
+// gitignore-style patterns from .ideepignore, relative to the content root
+#[derive(Default)]
+struct IgnoreRules {
+    rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreRules {
+    fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        if !path.exists() {
+            return Ok(IgnoreRules::default());
+        }
+        Ok(IgnoreRules::parse(&fs::read_to_string(path)?))
+    }
+
+    fn parse(text: &str) -> Self {
+        let rules = text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let (negated, line) = match line.strip_prefix('!') {
+                    Some(rest) => (true, rest),
+                    None => (false, line),
+                };
+                let dir_only = line.ends_with('/');
+                let line = line.trim_end_matches('/');
+                IgnoreRule {
+                    anchored: line.contains('/'),
+                    pattern: line.trim_start_matches('/').to_string(),
+                    dir_only,
+                    negated,
+                }
+            })
+            .collect();
+        IgnoreRules { rules }
+    }
+
+    fn is_ignored(&self, relative_path: &str, is_dir: bool) -> bool {
+        // Like gitignore, the last matching rule wins
+        let name = relative_path.rsplit('/').next().unwrap_or(relative_path);
+        let mut ignored = false;
+        for rule in &self.rules {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let matched = if rule.anchored {
+                glob_match(&rule.pattern, relative_path)
+                    || rule.pattern.strip_prefix("**/").is_some_and(|rest| glob_match(rest, relative_path))
+            } else {
+                glob_match(&rule.pattern, name)
+            };
+            if matched {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+fn order_entry_matches(entry: &str, key: &str) -> bool {
+    // Config entries match a full key, a filename, or (with `*`) a glob over keys
+    if entry.contains('*') {
+        glob_match(entry, key)
+    } else {
+        entry == key || key.ends_with(&format!("/{}", entry))
+    }
+}
+
+fn location_matches(entry: &str, key: &str) -> bool {
+    // A page, glob, or directory ("blog" or "blog/" covers everything under it)
+    let entry = entry.trim().trim_end_matches('/');
+    order_entry_matches(entry, key) || key.starts_with(&format!("{}/", entry))
+}
+
+fn find_order_matches<'a>(markdown_files: &'a [(PathBuf, PathBuf, String)], entry: &str) -> Vec<&'a (PathBuf, PathBuf, String)> {
+    // Globs expand to every matching page in key order; plain names pick the first match
+    if entry.contains('*') {
+        let mut matched: Vec<_> = markdown_files.iter()
+            .filter(|(_, rel_path, _)| glob_match(entry, &page_key(rel_path)))
+            .collect();
+        matched.sort_by_key(|(_, rel_path, _)| page_key(rel_path));
+        matched
+    } else {
+        markdown_files.iter()
+            .find(|(_, rel_path, _)| order_entry_matches(entry, &page_key(rel_path)))
+            .into_iter()
+            .collect()
+    }
 }
 
 fn extract_frontmatter(content: &str) -> (Option<FrontMatter>, &str) {
-    if content.starts_with("---\n") {
-        if let Some(end) = content[4..].find("---\n") {
-            let frontmatter_str = &content[4..end + 4];
-            let markdown_content = &content[end + 8..];
-            
-            match serde_yaml::from_str::<FrontMatter>(frontmatter_str) {
-                Ok(fm) => (Some(fm), markdown_content),
-                Err(_) => (None, content),
+    // Front matter that fails to parse is treated as part of the page
+    match parse_frontmatter(content) {
+        Ok(Some((fm, markdown_content))) => (Some(fm), markdown_content),
+        Ok(None) | Err(_) => (None, content),
+    }
+}
+
+fn parse_frontmatter(content: &str) -> Result<Option<(FrontMatter, &str)>, String> {
+    if let Some((frontmatter_str, markdown_content)) = split_frontmatter(content, "---") {
+        // Only a mapping (or an empty block) is front matter. Other YAML, like the prose
+        // between a leading thematic break and a later `---`, leaves the page as it is.
+        match serde_yaml::from_str::<serde_yaml::Value>(frontmatter_str) {
+            Ok(serde_yaml::Value::Mapping(_) | serde_yaml::Value::Null) | Err(_) => {
+                serde_yaml::from_str::<FrontMatter>(frontmatter_str)
+                    .map(|fm| Some((fm, markdown_content)))
+                    .map_err(|e| e.to_string())
             }
-        } else {
-            (None, content)
+            Ok(_) => Ok(None),
         }
+    } else if let Some((frontmatter_str, markdown_content)) = split_frontmatter(content, "+++") {
+        // Hugo-style TOML front matter
+        toml::from_str::<toml::Value>(frontmatter_str)
+            .and_then(|value| toml_dates_to_strings(value).try_into::<FrontMatter>())
+            .map(|fm| Some((fm, markdown_content)))
+            .map_err(|e| e.to_string())
     } else {
-        (None, content)
+        Ok(None)
+    }
+}
+
+// Snippets for {% include "name.md" %} live here and are never rendered as pages
+const INCLUDES_DIR: &str = "_includes";
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+fn expand_data_template(markdown: &str, data: &serde_yaml::Value, source: &Path) -> String {
+    // Only for pages with a `data` block: `{% for member in data.team %}...{% endfor %}` repeats
+    // its body per item (loops nest) and `{{ member.name }}` inserts an escaped value.
+    // Keys are dotted paths from `data` or a loop variable; list items take an index (data.team.0).
+    fn lookup<'a>(path: &str, scope: &[(&str, &'a serde_yaml::Value)]) -> Option<&'a serde_yaml::Value> {
+        let mut parts = path.split('.');
+        let first = parts.next()?;
+        let mut value = scope.iter().rev().find(|(name, _)| *name == first)?.1;
+        for part in parts {
+            value = match value {
+                serde_yaml::Value::Sequence(items) => items.get(part.parse::<usize>().ok()?)?,
+                _ => value.get(part)?,
+            };
+        }
+        Some(value)
+    }
+
+    // Compiled once, since render calls itself for every loop iteration
+    static TAG_PATTERN: std::sync::OnceLock<Regex> = std::sync::OnceLock::new();
+    static VALUE_PATTERN: std::sync::OnceLock<Regex> = std::sync::OnceLock::new();
+
+    fn render<'a>(text: &'a str, scope: &mut Vec<(&'a str, &serde_yaml::Value)>, source: &Path) -> String {
+        let tag_pattern = TAG_PATTERN.get_or_init(|| {
+            Regex::new(r"\{%\s*(?:for\s+(\w+)\s+in\s+([\w.]+)|endfor)\s*%\}").unwrap()
+        });
+        let value_pattern = VALUE_PATTERN.get_or_init(|| Regex::new(r"\{\{\s*([\w.]+)\s*\}\}").unwrap());
+        let interpolate = |text: &str, scope: &[(&str, &serde_yaml::Value)]| {
+            value_pattern.replace_all(text, |caps: &regex::Captures| {
+                match lookup(&caps[1], scope) {
+                    Some(serde_yaml::Value::String(s)) => escape_html(s),
+                    Some(serde_yaml::Value::Number(n)) => n.to_string(),
+                    Some(serde_yaml::Value::Bool(b)) => b.to_string(),
+                    Some(serde_yaml::Value::Null) => String::new(),
+                    Some(_) => {
+                        log_warn!("'{{{{ {} }}}}' in {} is a list or mapping, not a value", &caps[1], source.display());
+                        String::new()
+                    }
+                    None => {
+                        log_warn!("Unknown value '{{{{ {} }}}}' in {}", &caps[1], source.display());
+                        String::new()
+                    }
+                }
+            }).into_owned()
+        };
+
+        let mut result = String::new();
+        let mut rest = text;
+        while let Some(open) = tag_pattern.captures(rest) {
+            let open_match = open.get(0).unwrap();
+            result.push_str(&interpolate(&rest[..open_match.start()], scope));
+            let (Some(variable), Some(path)) = (open.get(1), open.get(2)) else {
+                log_warn!("Unmatched {{% endfor %}} in {}", source.display());
+                rest = &rest[open_match.end()..];
+                continue;
+            };
+            // Find the matching endfor, skipping over nested loops
+            let after = &rest[open_match.end()..];
+            let mut depth = 0;
+            let close = tag_pattern.captures_iter(after).find(|tag| {
+                if tag.get(1).is_some() {
+                    depth += 1;
+                    false
+                } else if depth == 0 {
+                    true
+                } else {
+                    depth -= 1;
+                    false
+                }
+            });
+            let Some(close) = close.and_then(|tag| tag.get(0)) else {
+                log_warn!("{{% for {} in {} %}} without {{% endfor %}} in {}", variable.as_str(), path.as_str(), source.display());
+                result.push_str(&interpolate(after, scope));
+                return result;
+            };
+            let body = &after[..close.start()];
+            match lookup(path.as_str(), scope) {
+                Some(serde_yaml::Value::Sequence(items)) => {
+                    for item in items {
+                        scope.push((variable.as_str(), item));
+                        result.push_str(&render(body, scope, source));
+                        scope.pop();
+                    }
+                }
+                _ => {
+                    log_warn!("'{}' in {} is not a list in the page's data", path.as_str(), source.display());
+                }
+            }
+            rest = &after[close.end()..];
+        }
+        result.push_str(&interpolate(rest, scope));
+        result
+    }
+
+    render(markdown, &mut vec![("data", data)], source)
+}
+
+fn expand_includes(markdown: &str, includes_dirs: &[PathBuf], stack: &mut Vec<PathBuf>) -> Result<String, Box<dyn std::error::Error>> {
+    // `stack` holds the page followed by the includes currently being expanded.
+    // With several content roots the last one providing a snippet wins.
+    let include_pattern = Regex::new(r#"\{%\s*include\s+"([^"]+)"\s*%\}"#).unwrap();
+    let mut result = String::with_capacity(markdown.len());
+    let mut in_fence = false;
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        if in_fence {
+            result.push_str(line);
+            continue;
+        }
+        let mut last_end = 0;
+        for cap in include_pattern.captures_iter(line) {
+            let full_match = cap.get(0).unwrap();
+            result.push_str(&line[last_end..full_match.start()]);
+            last_end = full_match.end();
+
+            let path = includes_dirs.iter().rev()
+                .map(|dir| dir.join(&cap[1]))
+                .find(|path| path.exists())
+                .unwrap_or_else(|| includes_dirs.last().map(|dir| dir.join(&cap[1])).unwrap_or_default());
+            let chain = || stack.iter().chain(std::iter::once(&path))
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            if stack.contains(&path) {
+                return Err(format!("Include cycle: {}", chain()).into());
+            }
+            if stack.len() > MAX_INCLUDE_DEPTH {
+                return Err(format!("Includes nested more than {} deep: {}", MAX_INCLUDE_DEPTH, chain()).into());
+            }
+            let content = fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to include {} in {}: {}", path.display(), stack[0].display(), e))?;
+            let (_, body) = extract_frontmatter(&content);
+            stack.push(path);
+            let expanded = expand_includes(body, includes_dirs, stack)?;
+            stack.pop();
+            result.push_str(expanded.trim_end_matches('\n'));
+        }
+        result.push_str(&line[last_end..]);
+    }
+    Ok(result)
+}
+
+fn csv_to_html_table(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    // First row is the header; rows of differing length are reported as malformed
+    let table_error = |e: csv::Error| format!("Failed to read table {}: {}", path.display(), e);
+    let mut reader = csv::Reader::from_path(path).map_err(table_error)?;
+    let mut html = String::from("<table class=\"data-table\">\n<thead>\n<tr>");
+    for header in reader.headers().map_err(table_error)? {
+        html.push_str(&format!("<th>{}</th>", escape_html(header)));
+    }
+    html.push_str("</tr>\n</thead>\n<tbody>\n");
+    for record in reader.records() {
+        html.push_str("<tr>");
+        for cell in &record.map_err(table_error)? {
+            html.push_str(&format!("<td>{}</td>", escape_html(cell)));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody>\n</table>\n");
+    Ok(html)
+}
+
+fn expand_tables(markdown: &str, data_dir: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    // {% table "results.csv" %} becomes an HTML table block; fenced code is left alone
+    let table_pattern = Regex::new(r#"\{%\s*table\s+"([^"]+)"\s*%\}"#).unwrap();
+    let mut result = String::with_capacity(markdown.len());
+    let mut in_fence = false;
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        if in_fence {
+            result.push_str(line);
+            continue;
+        }
+        let mut last_end = 0;
+        for cap in table_pattern.captures_iter(line) {
+            let full_match = cap.get(0).unwrap();
+            result.push_str(&line[last_end..full_match.start()]);
+            last_end = full_match.end();
+            // Blank lines around the table keep it a separate HTML block
+            result.push_str("\n\n");
+            result.push_str(&csv_to_html_table(&data_dir.join(&cap[1]))?);
+            result.push('\n');
+        }
+        result.push_str(&line[last_end..]);
+    }
+    Ok(result)
+}
+
+fn resolve_wikilinks(markdown: &str, titles: &std::collections::HashMap<String, String>, source: &Path) -> String {
+    // [[page]], [[page|label]] and [[page#section]] become ordinary markdown links to the
+    // page key, matched case-insensitively by key, filename or title. Code is left alone.
+    let wikilink_pattern = Regex::new(r"(!?)\[\[([^\[\]|]+?)(?:\|([^\[\]]+?))?\]\]").unwrap();
+    let find_key = |target: &str| -> Option<&String> {
+        let target = target.trim().to_lowercase();
+        let mut keys: Vec<&String> = titles.keys().collect();
+        keys.sort();
+        keys.iter().find(|key| key.to_lowercase() == target)
+            .or_else(|| keys.iter().find(|key| key.rsplit('/').next().unwrap_or(key).to_lowercase() == target))
+            .or_else(|| keys.iter().find(|key| titles[key.as_str()].to_lowercase() == target))
+            .copied()
+    };
+    let mut result = String::with_capacity(markdown.len());
+    let mut in_fence = false;
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        if in_fence {
+            result.push_str(line);
+            continue;
+        }
+        // Even-numbered pieces between backticks are outside code spans
+        for (i, piece) in line.split('`').enumerate() {
+            if i > 0 {
+                result.push('`');
+            }
+            if i % 2 == 1 {
+                result.push_str(piece);
+                continue;
+            }
+            let replaced = wikilink_pattern.replace_all(piece, |caps: &regex::Captures| {
+                if !caps[1].is_empty() {
+                    // Obsidian embeds (![[...]]) aren't supported
+                    return caps[0].to_string();
+                }
+                let (target, fragment) = match caps[2].split_once('#') {
+                    Some((target, fragment)) => (target, format!("#{}", slugify(fragment))),
+                    None => (&caps[2], String::new()),
+                };
+                let label = caps.get(3).map(|m| m.as_str()).unwrap_or(&caps[2]).trim();
+                match find_key(target) {
+                    Some(key) => format!("[{}](<{}{}>)", label, key, fragment),
+                    None => {
+                        log_warn!("Unresolved wikilink '[[{}]]' in {}", &caps[2], source.display());
+                        label.to_string()
+                    }
+                }
+            });
+            result.push_str(&replaced);
+        }
+    }
+    result
+}
+
+// Effects of `<!-- ideeep:... -->` comments found in a page
+#[derive(Debug, Default)]
+struct Directives {
+    no_navbar: bool,  // ideeep:no-navbar
+    no_math: bool,    // ideeep:no-math, leaves $ signs untouched
+    no_search: bool,  // ideeep:no-search, keeps the page out of search-index.json
+}
+
+fn extract_directives(markdown: &str, source: &Path) -> (String, Directives) {
+    // Known directives are applied and removed; anything else stays as a comment.
+    // Fenced code blocks are left alone so directives can be documented.
+    let directive_pattern = Regex::new(r"<!--\s*ideeep:([A-Za-z0-9_-]+)\s*-->").unwrap();
+    let mut directives = Directives::default();
+    let mut result = String::with_capacity(markdown.len());
+    let mut in_fence = false;
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        if in_fence {
+            result.push_str(line);
+            continue;
+        }
+        let replaced = directive_pattern.replace_all(line, |caps: &regex::Captures| {
+            match &caps[1] {
+                "no-navbar" => directives.no_navbar = true,
+                "no-math" => directives.no_math = true,
+                "no-search" => directives.no_search = true,
+                other => {
+                    log_warn!("Unknown directive 'ideeep:{}' in {}", other, source.display());
+                    return caps[0].to_string();
+                }
+            }
+            String::new()
+        });
+        result.push_str(&replaced);
     }
+    (result, directives)
 }
 
-fn katex_opts(display: bool) -> Opts {
+fn katex_opts(display: bool, macros: &std::collections::HashMap<String, String>, throw_on_error: bool) -> Opts {
     katex::Opts::builder()
         .display_mode(display)
-        .throw_on_error(false)
+        .macros(macros.clone())
+        .throw_on_error(throw_on_error)
         .output_type(OutputType::HtmlAndMathml)
         .build()
         .unwrap()
 }
 
-fn preprocess_math(md: &str) -> String {
+// Renders TeX with KaTeX, caching results so repeated expressions render once per build
+struct MathRenderer {
+    cache: std::collections::HashMap<(String, bool), String>,
+    pending: Vec<String>,  // Rendered math for the current page, keyed by placeholder index
+    macros: std::collections::HashMap<String, String>,  // From katex_macros in config
+    preserve_newlines: bool,  // From math_preserve_newlines in config
+    strict: bool,             // From math_strict in config or --strict
+    delimiters: MathDelimiters,
+    errors: Vec<(String, String)>,  // (tex, message) of failed expressions on the current page, in strict mode
+}
+
+// The delimiter pairs preprocess_math recognizes, from math_delimiters in config
+#[derive(Debug, Clone, Copy)]
+struct MathDelimiters {
+    dollar: bool,         // $...$ inline
+    double_dollar: bool,  // $$...$$ display
+    paren: bool,          // \(...\) inline
+    bracket: bool,        // \[...\] display
+}
+
+impl MathDelimiters {
+    fn from_config(configured: Option<&Vec<String>>) -> Self {
+        let Some(names) = configured else {
+            return MathDelimiters { dollar: true, double_dollar: true, paren: true, bracket: true };
+        };
+        let mut delimiters = MathDelimiters { dollar: false, double_dollar: false, paren: false, bracket: false };
+        for name in names {
+            match name.trim() {
+                "$" => delimiters.dollar = true,
+                "$$" => delimiters.double_dollar = true,
+                "\\(" => delimiters.paren = true,
+                "\\[" => delimiters.bracket = true,
+                other => {
+                    log_warn!("Unknown math delimiter '{}'; expected $, $$, \\( or \\[", other);
+                }
+            }
+        }
+        delimiters
+    }
+}
+
+// Placeholders are private-use characters that markdown parsing leaves untouched
+const MATH_PLACEHOLDER_START: char = '\u{E000}';
+const MATH_PLACEHOLDER_END: char = '\u{E001}';
+
+impl MathRenderer {
+    fn new(macros: std::collections::HashMap<String, String>, preserve_newlines: bool, strict: bool, delimiters: MathDelimiters) -> Self {
+        MathRenderer {
+            cache: std::collections::HashMap::new(),
+            pending: Vec::new(),
+            macros,
+            preserve_newlines,
+            strict,
+            delimiters,
+            errors: Vec::new(),
+        }
+    }
+
+    fn placeholder(&mut self, html: String) -> String {
+        // Keep KaTeX output away from the markdown parser (smart punctuation, emphasis, ...)
+        self.pending.push(html);
+        format!("{}{}{}", MATH_PLACEHOLDER_START, self.pending.len() - 1, MATH_PLACEHOLDER_END)
+    }
+
+    fn restore(&mut self, html: &str) -> String {
+        let placeholder_pattern = Regex::new(&format!("{}(\\d+){}", MATH_PLACEHOLDER_START, MATH_PLACEHOLDER_END)).unwrap();
+        let restored = placeholder_pattern.replace_all(html, |caps: &regex::Captures| {
+            caps[1].parse::<usize>().ok()
+                .and_then(|i| self.pending.get(i))
+                .cloned()
+                .unwrap_or_default()
+        }).into_owned();
+        self.pending.clear();
+        restored
+    }
+
+    fn render(&mut self, tex: &str, display: bool) -> Result<String, katex::Error> {
+        let key = (normalize_tex(tex, self.preserve_newlines), display);
+        if let Some(html) = self.cache.get(&key) {
+            return Ok(html.clone());
+        }
+        // Leniently, KaTeX renders parse errors inline in red; strictly they come back as
+        // errors and are collected for build_site to report
+        let html = katex::render_with_opts(&key.0, katex_opts(display, &self.macros, self.strict))
+            .inspect_err(|e| {
+                if self.strict {
+                    self.errors.push((key.0.clone(), katex_error_message(e)));
+                }
+            })?;
+        self.cache.insert(key, html.clone());
+        Ok(html)
+    }
+
+    fn take_errors(&mut self) -> Vec<(String, String)> {
+        std::mem::take(&mut self.errors)
+    }
+}
+
+fn katex_error_message(error: &katex::Error) -> String {
+    // A parse error arrives as the debug form of the JS exception; keep just KaTeX's message
+    let detail = match error {
+        katex::Error::JsExecError(detail) => detail,
+        _ => return error.to_string(),
+    };
+    match detail.split_once("KaTeX parse error: ") {
+        Some((_, message)) => message.trim_end_matches("\")").replace("\\\\", "\\").replace("\\\"", "\""),
+        None => error.to_string(),
+    }
+}
+
+fn normalize_tex(tex: &str, preserve_newlines: bool) -> String {
+    // Each line is trimmed and blank lines are dropped, so `$$ \n x \n $$` and `$$x$$` render
+    // the same. Lines are then joined with spaces, unless newlines are preserved (e.g. for
+    // readable `aligned` sources) or a `%` comment needs its line ending to stop.
+    let has_comment = tex.lines().any(|line| {
+        line.char_indices().any(|(i, c)| c == '%' && !line[..i].ends_with('\\'))
+    });
+    let lines: Vec<&str> = tex.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+    lines.join(if preserve_newlines || has_comment { "\n" } else { " " })
+}
+
+fn push_display_math(result: &mut String, placeholder: &str, rest_of_line: &str) {
+    // Display math on a line of its own becomes an HTML block, so the markdown
+    // parser doesn't wrap it in a stray <p> along with the surrounding text
+    let line_start = result.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let indent = result[line_start..].to_string();
+    if indent.trim().is_empty() && rest_of_line.trim().is_empty() {
+        result.truncate(line_start);
+        result.push_str(&format!("\n{}<div class=\"math-display\">{}</div>\n", indent, placeholder));
+    } else {
+        result.push_str(placeholder);
+    }
+}
+
+fn preprocess_math(md: &str, math: &mut MathRenderer) -> String {
     let mut result = String::with_capacity(md.len() * 2);
     let mut chars = md.chars().peekable();
     
     while let Some(ch) = chars.next() {
         if ch == '$' {
+            let delimiters = math.delimiters;
             // Check for display math: $$
-            if chars.peek() == Some(&'$') {
+            if chars.peek() == Some(&'$') && !delimiters.double_dollar {
+                // Left as text, rather than read as two inline delimiters
+                chars.next();
+                result.push_str("$$");
+            } else if chars.peek() == Some(&'$') {
                 chars.next(); // consume second $
                 let mut tex = String::new();
                 let mut found_end = false;
@@ -63,9 +972,10 @@ fn preprocess_math(md: &str) -> String {
                     tex.push(c);
                 }
                 if found_end {
-                    let html = katex::render_with_opts(tex.trim(), katex_opts(true))
+                    let html = math.render(&tex, true)
                         .unwrap_or_else(|_| format!(r#"<pre class="math-error">{}</pre>"#, tex));
-                    result.push_str(&html);
+                    let rest_of_line: String = chars.clone().take_while(|c| *c != '\n').collect();
+                    push_display_math(&mut result, &math.placeholder(html), &rest_of_line);
                 } else {
                     // Not a valid display math, put it back
                     result.push('$');
@@ -73,37 +983,26 @@ fn preprocess_math(md: &str) -> String {
                     result.push_str(&tex);
                 }
             } else {
-                // Inline math: $...$
-                let mut tex = String::new();
-                let mut found_end = false;
-                while let Some(c) = chars.next() {
-                    if c == '$' {
-                        found_end = true;
-                        break;
-                    }
-                    if c == '\n' {
-                        // Inline math can't span lines, put it back
-                        result.push('$');
-                        result.push_str(&tex);
-                        result.push(c);
-                        tex.clear();
-                        break;
-                    }
-                    tex.push(c);
-                }
-                if found_end && !tex.is_empty() {
-                    let html = katex::render_with_opts(tex.trim(), katex_opts(false))
+                // Inline math: $...$ on a single line. A span that starts with a digit and
+                // ends in whitespace is currency, as in "costs $5 today and $x tomorrow", so
+                // only the `$` is emitted and its closing `$` gets a chance to open math instead.
+                let tex: String = chars.clone().take_while(|c| *c != '$' && *c != '\n').collect();
+                let tex_len = tex.chars().count();
+                let found_end = chars.clone().nth(tex_len) == Some('$');
+                let is_currency = tex.starts_with(|c: char| c.is_ascii_digit()) && tex.ends_with(char::is_whitespace);
+                if delimiters.dollar && found_end && !tex.is_empty() && !is_currency {
+                    chars.nth(tex_len); // consume the tex and the closing $
+                    let html = math.render(&tex, false)
                         .unwrap_or_else(|_| format!(r#"<code class="math-error">{}</code>"#, tex));
-                    result.push_str(&html);
+                    result.push_str(&math.placeholder(html));
                 } else {
                     result.push('$');
-                    result.push_str(&tex);
                 }
             }
         } else if ch == '\\' {
             // Check for \( or \[
             if let Some(&next) = chars.peek() {
-                if next == '(' {
+                if next == '(' && math.delimiters.paren {
                     chars.next(); // consume (
                     let mut tex = String::new();
                     let mut found_end = false;
@@ -116,15 +1015,15 @@ fn preprocess_math(md: &str) -> String {
                         tex.push(c);
                     }
                     if found_end {
-                        let html = katex::render_with_opts(tex.trim(), katex_opts(false))
+                        let html = math.render(&tex, false)
                             .unwrap_or_else(|_| format!(r#"<code class="math-error">{}</code>"#, tex));
-                        result.push_str(&html);
+                        result.push_str(&math.placeholder(html));
                     } else {
                         result.push('\\');
                         result.push('(');
                         result.push_str(&tex);
                     }
-                } else if next == '[' {
+                } else if next == '[' && math.delimiters.bracket {
                     chars.next(); // consume [
                     let mut tex = String::new();
                     let mut found_end = false;
@@ -137,9 +1036,10 @@ fn preprocess_math(md: &str) -> String {
                         tex.push(c);
                     }
                     if found_end {
-                        let html = katex::render_with_opts(tex.trim(), katex_opts(true))
+                        let html = math.render(&tex, true)
                             .unwrap_or_else(|_| format!(r#"<pre class="math-error">{}</pre>"#, tex));
-                        result.push_str(&html);
+                        let rest_of_line: String = chars.clone().take_while(|c| *c != '\n').collect();
+                        push_display_math(&mut result, &math.placeholder(html), &rest_of_line);
                     } else {
                         result.push('\\');
                         result.push('[');
@@ -159,7 +1059,14 @@ fn preprocess_math(md: &str) -> String {
     result
 }
 
-fn convert_internal_links(html: &str, markdown_files: &std::collections::HashSet<String>) -> String {
+fn convert_internal_links(html: &str, markdown_files: &std::collections::HashMap<String, String>, base_path: &str, from_path: &Path) -> String {
+    // `markdown_files` maps every known page key (source name and slug) to its output key
+    let find_target = |name: &str| {
+        markdown_files.iter()
+            .find(|(key, _)| key.as_str() == name || key.ends_with(&format!("/{}", name)))
+            .map(|(_, target)| target.as_str())
+    };
+
     // Create a regex to match <a href="..."> tags
     let link_pattern = Regex::new(r#"<a\s+href="([^"]+)"([^>]*)>"#).unwrap();
     let mut result = html.to_string();
@@ -179,33 +1086,60 @@ fn convert_internal_links(html: &str, markdown_files: &std::collections::HashSet
             continue;
         }
         
-        // Split href into base and fragment/query
-        let (base_href, fragment_query) = if let Some(pos) = href.find('#') {
-            let (base, rest) = href.split_at(pos);
-            (base, Some(rest))
-        } else if let Some(pos) = href.find('?') {
-            let (base, rest) = href.split_at(pos);
-            (base, Some(rest))
-        } else {
-            (href, None)
+        // Split href into base and query/fragment at whichever comes first,
+        // so `sir?x=1#results` keeps both when rewritten
+        let (base_href, fragment_query) = match href.find(['#', '?']) {
+            Some(pos) => {
+                let (base, rest) = href.split_at(pos);
+                (base, Some(rest))
+            }
+            None => (href, None),
         };
         
-        let new_href = if base_href.ends_with(".md") {
-            // Replace .md with .html
-            let mut new = base_href.replace(".md", ".html");
+        let new_href = if let Some(stem) = strip_markdown_extension(base_href) {
+            // The path is relative to the page; resolved to a key from the site root it
+            // follows any slug rename and gets the same base path as bare-name links
+            let resolved = normalize_relative_path(&from_path.parent().unwrap_or(Path::new("")).join(stem))
+                .map(|path| path.to_string_lossy().replace('\\', "/"));
+            let mut new = match resolved {
+                Some(key) => {
+                    let target = markdown_files.get(&key).unwrap_or(&key);
+                    if base_path.is_empty() {
+                        calculate_relative_link_path(from_path, target)
+                    } else {
+                        format!("{}{}.html", base_path, target)
+                    }
+                }
+                // Climbs above the site root, so there's nothing to resolve it to
+                None => {
+                    let file_name = find_target(stem)
+                        .and_then(|target| target.rsplit('/').next())
+                        .unwrap_or_else(|| stem.rsplit('/').next().unwrap_or(stem));
+                    let dir = stem.rsplit_once('/').map(|(d, _)| format!("{}/", d)).unwrap_or_default();
+                    format!("{}{}.html", dir, file_name)
+                }
+            };
             if let Some(fq) = fragment_query {
                 new.push_str(fq);
             }
             new
         } else if !base_href.contains('.') {
-            // Check if it matches a markdown file (by exact match or filename match)
-            let matched_path = markdown_files.iter()
-                .find(|path| {
-                    path.as_str() == base_href || path.ends_with(&format!("/{}", base_href))
-                });
-            
-            if let Some(matched) = matched_path {
-                let mut new = format!("{}.html", matched);
+            // Check if it matches a markdown file (by exact match or filename match),
+            // or a directory with an index page: `docs` or `docs/` -> docs/index.html
+            let dir_index = || find_target(&format!("{}/index", base_href.trim_end_matches('/')));
+            let matched = if base_href.ends_with('/') {
+                dir_index()
+            } else {
+                find_target(base_href).or_else(dir_index)
+            };
+            if let Some(matched) = matched {
+                // Matched keys are relative to the site root; without a base path
+                // the link is made relative to the page it appears on
+                let mut new = if base_path.is_empty() {
+                    calculate_relative_link_path(from_path, matched)
+                } else {
+                    format!("{}{}.html", base_path, matched)
+                };
                 if let Some(fq) = fragment_query {
                     new.push_str(fq);
                 }
@@ -231,32 +1165,737 @@ fn convert_internal_links(html: &str, markdown_files: &std::collections::HashSet
     result
 }
 
-fn markdown_to_html(markdown: &str, markdown_files: &std::collections::HashSet<String>) -> String {
+// GitHub-style emoji shortcodes, without the surrounding colons
+const EMOJI_SHORTCODES: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("thumbsup", "👍"),
+    ("thumbsdown", "👎"),
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("grin", "😁"),
+    ("laughing", "😆"),
+    ("joy", "😂"),
+    ("wink", "😉"),
+    ("blush", "😊"),
+    ("heart_eyes", "😍"),
+    ("thinking", "🤔"),
+    ("neutral_face", "😐"),
+    ("confused", "😕"),
+    ("worried", "😟"),
+    ("cry", "😢"),
+    ("sob", "😭"),
+    ("scream", "😱"),
+    ("sweat_smile", "😅"),
+    ("sunglasses", "😎"),
+    ("nerd_face", "🤓"),
+    ("upside_down_face", "🙃"),
+    ("slightly_smiling_face", "🙂"),
+    ("wave", "👋"),
+    ("clap", "👏"),
+    ("raised_hands", "🙌"),
+    ("pray", "🙏"),
+    ("muscle", "💪"),
+    ("ok_hand", "👌"),
+    ("point_right", "👉"),
+    ("point_left", "👈"),
+    ("point_up", "☝️"),
+    ("point_down", "👇"),
+    ("eyes", "👀"),
+    ("brain", "🧠"),
+    ("heart", "❤️"),
+    ("broken_heart", "💔"),
+    ("sparkles", "✨"),
+    ("star", "⭐"),
+    ("star2", "🌟"),
+    ("fire", "🔥"),
+    ("zap", "⚡"),
+    ("boom", "💥"),
+    ("tada", "🎉"),
+    ("confetti_ball", "🎊"),
+    ("gift", "🎁"),
+    ("trophy", "🏆"),
+    ("medal_sports", "🏅"),
+    ("rocket", "🚀"),
+    ("airplane", "✈️"),
+    ("car", "🚗"),
+    ("bike", "🚲"),
+    ("globe_with_meridians", "🌐"),
+    ("earth_americas", "🌎"),
+    ("sunny", "☀️"),
+    ("cloud", "☁️"),
+    ("umbrella", "☔"),
+    ("snowflake", "❄️"),
+    ("rainbow", "🌈"),
+    ("seedling", "🌱"),
+    ("evergreen_tree", "🌲"),
+    ("coffee", "☕"),
+    ("pizza", "🍕"),
+    ("apple", "🍎"),
+    ("cake", "🍰"),
+    ("bug", "🐛"),
+    ("microbe", "🦠"),
+    ("dna", "🧬"),
+    ("test_tube", "🧪"),
+    ("microscope", "🔬"),
+    ("telescope", "🔭"),
+    ("pill", "💊"),
+    ("syringe", "💉"),
+    ("computer", "💻"),
+    ("keyboard", "⌨️"),
+    ("desktop_computer", "🖥️"),
+    ("floppy_disk", "💾"),
+    ("cd", "💿"),
+    ("iphone", "📱"),
+    ("gear", "⚙️"),
+    ("wrench", "🔧"),
+    ("hammer", "🔨"),
+    ("hammer_and_wrench", "🛠️"),
+    ("nut_and_bolt", "🔩"),
+    ("toolbox", "🧰"),
+    ("link", "🔗"),
+    ("lock", "🔒"),
+    ("unlock", "🔓"),
+    ("key", "🔑"),
+    ("mag", "🔍"),
+    ("bulb", "💡"),
+    ("bell", "🔔"),
+    ("pushpin", "📌"),
+    ("paperclip", "📎"),
+    ("memo", "📝"),
+    ("pencil", "📝"),
+    ("pencil2", "✏️"),
+    ("book", "📖"),
+    ("books", "📚"),
+    ("notebook", "📓"),
+    ("page_facing_up", "📄"),
+    ("clipboard", "📋"),
+    ("calendar", "📆"),
+    ("date", "📅"),
+    ("chart_with_upwards_trend", "📈"),
+    ("chart_with_downwards_trend", "📉"),
+    ("bar_chart", "📊"),
+    ("package", "📦"),
+    ("email", "📧"),
+    ("envelope", "✉️"),
+    ("inbox_tray", "📥"),
+    ("outbox_tray", "📤"),
+    ("mailbox", "📫"),
+    ("hourglass", "⌛"),
+    ("stopwatch", "⏱️"),
+    ("alarm_clock", "⏰"),
+    ("watch", "⌚"),
+    ("white_check_mark", "✅"),
+    ("heavy_check_mark", "✔️"),
+    ("ballot_box_with_check", "☑️"),
+    ("x", "❌"),
+    ("heavy_multiplication_x", "✖️"),
+    ("warning", "⚠️"),
+    ("no_entry", "⛔"),
+    ("no_entry_sign", "🚫"),
+    ("construction", "🚧"),
+    ("rotating_light", "🚨"),
+    ("question", "❓"),
+    ("grey_question", "❔"),
+    ("exclamation", "❗"),
+    ("grey_exclamation", "❕"),
+    ("bangbang", "‼️"),
+    ("information_source", "ℹ️"),
+    ("new", "🆕"),
+    ("free", "🆓"),
+    ("up", "🆙"),
+    ("cool", "🆒"),
+    ("ok", "🆗"),
+    ("sos", "🆘"),
+    ("arrow_right", "➡️"),
+    ("arrow_left", "⬅️"),
+    ("arrow_up", "⬆️"),
+    ("arrow_down", "⬇️"),
+    ("arrows_counterclockwise", "🔄"),
+    ("heavy_plus_sign", "➕"),
+    ("heavy_minus_sign", "➖"),
+    ("100", "💯"),
+    ("one", "1️⃣"),
+    ("two", "2️⃣"),
+    ("three", "3️⃣"),
+    ("red_circle", "🔴"),
+    ("large_blue_circle", "🔵"),
+    ("green_circle", "🟢"),
+    ("yellow_circle", "🟡"),
+    ("dart", "🎯"),
+    ("art", "🎨"),
+    ("musical_note", "🎵"),
+    ("camera", "📷"),
+    ("video_camera", "📹"),
+    ("movie_camera", "🎥"),
+    ("house", "🏠"),
+    ("school", "🏫"),
+    ("hospital", "🏥"),
+    ("office", "🏢"),
+    ("dog", "🐶"),
+    ("cat", "🐱"),
+    ("mouse", "🐭"),
+    ("rabbit", "🐰"),
+    ("bee", "🐝"),
+    ("snake", "🐍"),
+    ("crab", "🦀"),
+    ("penguin", "🐧"),
+    ("bird", "🐦"),
+    ("unicorn", "🦄"),
+    ("mortar_board", "🎓"),
+    ("man_technologist", "👨‍💻"),
+    ("woman_technologist", "👩‍💻"),
+    ("busts_in_silhouette", "👥"),
+    ("bust_in_silhouette", "👤"),
+    ("speech_balloon", "💬"),
+    ("thought_balloon", "💭"),
+    ("loudspeaker", "📢"),
+    ("mega", "📣"),
+    ("lipstick", "💄"),
+    ("moneybag", "💰"),
+    ("dollar", "💵"),
+    ("chart", "💹"),
+    ("scales", "⚖️"),
+    ("shield", "🛡️"),
+    ("crossed_fingers", "🤞"),
+    ("see_no_evil", "🙈"),
+    ("skull", "💀"),
+    ("ghost", "👻"),
+    ("robot", "🤖"),
+    ("alien", "👽"),
+    ("poop", "💩"),
+    ("zzz", "💤"),
+    ("sweat_drops", "💦"),
+    ("droplet", "💧"),
+    ("ocean", "🌊"),
+    ("mountain", "⛰️"),
+];
+
+fn replace_emoji_shortcodes(text: &str) -> String {
+    // Unknown shortcodes, and things like times ("10:30:00"), are left as written
+    static SHORTCODE_PATTERN: std::sync::OnceLock<Regex> = std::sync::OnceLock::new();
+    let shortcode_pattern = SHORTCODE_PATTERN.get_or_init(|| Regex::new(r":([a-z0-9_+-]+):").unwrap());
+    shortcode_pattern.replace_all(text, |caps: &regex::Captures| {
+        EMOJI_SHORTCODES.iter()
+            .find(|(name, _)| *name == &caps[1])
+            .map(|(_, emoji)| emoji.to_string())
+            .unwrap_or_else(|| caps[0].to_string())
+    }).into_owned()
+}
+
+fn extract_abbreviations(markdown: &str) -> (String, Vec<(String, String)>) {
+    // `*[HTML]: HyperText Markup Language` lines are removed and collected; fences are left alone
+    static DEFINITION_PATTERN: std::sync::OnceLock<Regex> = std::sync::OnceLock::new();
+    let definition_pattern = DEFINITION_PATTERN.get_or_init(|| Regex::new(r"^\*\[([^\]]+)\]:[ \t]*(.+?)\s*$").unwrap());
+    let mut abbreviations = Vec::new();
+    let mut result = String::with_capacity(markdown.len());
+    let mut in_fence = false;
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        match definition_pattern.captures(line) {
+            Some(caps) if !in_fence => {
+                abbreviations.push((caps[1].trim().to_string(), caps[2].to_string()));
+            }
+            _ => result.push_str(line),
+        }
+    }
+    (result, abbreviations)
+}
+
+struct InlineExtensions {
+    kbd_pattern: Regex,
+    abbr_pattern: Option<Regex>,
+    abbr_titles: std::collections::HashMap<String, String>,  // Escaped term -> escaped title
+}
+
+impl InlineExtensions {
+    fn new(abbreviations: &[(String, String)]) -> Self {
+        // Compiled once per page, since they run on every text event
+        let kbd_pattern = Regex::new(r"(^|[^\w+])\+\+([^\s+](?:[^+]|\+[^+])*?)\+\+").unwrap();
+        let abbr_titles: std::collections::HashMap<String, String> = abbreviations.iter()
+            .map(|(term, title)| (escape_html(term), escape_html(title)))
+            .collect();
+        // Longest terms first so "HTML5" wins over "HTML"; existing tags are skipped
+        let mut terms: Vec<&String> = abbr_titles.keys().collect();
+        terms.sort_by_key(|term| std::cmp::Reverse(term.len()));
+        let abbr_pattern = (!terms.is_empty()).then(|| {
+            let alternatives: Vec<String> = terms.iter().map(|term| regex::escape(term)).collect();
+            Regex::new(&format!(r"(<[^>]*>)|\b({})\b", alternatives.join("|"))).unwrap()
+        });
+        InlineExtensions { kbd_pattern, abbr_pattern, abbr_titles }
+    }
+
+    fn render(&self, text: &str) -> Option<String> {
+        // ++Ctrl+C++ becomes <kbd>Ctrl</kbd>+<kbd>C</kbd>; defined abbreviations get <abbr> tooltips.
+        // Returns None when nothing matched so the text event can pass through untouched.
+        if !text.contains("++") && self.abbr_pattern.is_none() {
+            return None;
+        }
+        let escaped = escape_html(text);
+        let mut html = self.kbd_pattern.replace_all(&escaped, |caps: &regex::Captures| {
+            let keys: Vec<String> = caps[2].split('+').map(|key| format!("<kbd>{}</kbd>", key.trim())).collect();
+            format!("{}{}", &caps[1], keys.join("+"))
+        }).into_owned();
+
+        if let Some(ref abbr_pattern) = self.abbr_pattern {
+            html = abbr_pattern.replace_all(&html, |caps: &regex::Captures| {
+                let Some(term) = caps.get(2) else { return caps[0].to_string() };
+                format!("<abbr title=\"{}\">{}</abbr>", self.abbr_titles[term.as_str()], term.as_str())
+            }).into_owned();
+        }
+
+        (html != escaped).then_some(html)
+    }
+}
+
+fn mark_external_links(html: &str) -> String {
+    // Same treatment as external navbar links; anchors with an explicit target are left alone
+    let link_pattern = Regex::new(r#"<a\s+href="(https?://[^"]*)"([^>]*)>"#).unwrap();
+    link_pattern.replace_all(html, |caps: &regex::Captures| {
+        let attrs = &caps[2];
+        if attrs.contains("target=") {
+            return caps[0].to_string();
+        }
+        let rel = if attrs.contains("rel=") { "" } else { r#" rel="noopener noreferrer""# };
+        format!(r#"<a href="{}"{} target="_blank"{}>"#, &caps[1], attrs, rel)
+    }).into_owned()
+}
+
+fn style_task_lists(html: &str) -> String {
+    // pulldown-cmark emits bare disabled checkboxes; tag them so the stylesheet can drop bullets
+    let html = html.replace(
+        "<li><input disabled=\"\" type=\"checkbox\"",
+        "<li class=\"task-list-item\"><input class=\"task-list-checkbox\" disabled=\"\" type=\"checkbox\"",
+    );
+    html.replace("<ul>\n<li class=\"task-list-item\">", "<ul class=\"task-list\">\n<li class=\"task-list-item\">")
+}
+
+fn render_admonitions(html: &str) -> String {
+    // GitHub-style alerts: a blockquote starting with [!NOTE], [!TIP], [!WARNING], ...
+    let alert_pattern = Regex::new(
+        r"(?s)<blockquote>\n<p>\[!(NOTE|TIP|IMPORTANT|WARNING|CAUTION|DANGER)\][ \t]*(.*?)</blockquote>"
+    ).unwrap();
+    alert_pattern.replace_all(html, |caps: &regex::Captures| {
+        let kind = caps[1].to_lowercase();
+        let (label, icon) = match kind.as_str() {
+            "note" => ("Note", "fa-circle-info"),
+            "tip" => ("Tip", "fa-lightbulb"),
+            "important" => ("Important", "fa-circle-exclamation"),
+            "warning" => ("Warning", "fa-triangle-exclamation"),
+            "caution" => ("Caution", "fa-hand"),
+            _ => ("Danger", "fa-radiation"),
+        };
+        // The marker either shares the first paragraph with text or sits alone in it
+        let body = caps[2].trim_start();
+        let body = match body.strip_prefix("</p>") {
+            Some(rest) => rest.trim_start().to_string(),
+            None => format!("<p>{}", body),
+        };
+        format!(
+            "<div class=\"admonition {}\">\n<p class=\"admonition-title\"><i class=\"fa-solid {}\" aria-hidden=\"true\"></i> {}</p>\n{}</div>",
+            kind, icon, label, body
+        )
+    }).into_owned()
+}
+
+fn slugify(text: &str) -> String {
+    // The one slug rule for anchors: "  Hello, World! " -> "hello-world". Letters and digits
+    // of any script are kept, whitespace and dashes collapse to single dashes, everything
+    // else is dropped; a heading of only punctuation becomes "section".
+    let mut slug = String::with_capacity(text.len());
+    for c in text.trim().chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if (c.is_whitespace() || c == '-') && !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() { "section".to_string() } else { slug.to_string() }
+}
+
+fn add_heading_ids(html: &str) -> String {
+    // Headings get an id from slugify of their text, -1, -2... on repeats within the page.
+    // Ids set with `{#id}` are kept. Runs before math is restored, so a formula in a heading
+    // is just a placeholder and doesn't end up in the slug.
+    let heading_pattern = Regex::new(r"(?s)<h([1-6])(\s[^>]*)?>(.*?)</h[1-6]>").unwrap();
+    let tag_pattern = Regex::new(&format!(r"<[^>]*>|{}\d+{}", MATH_PLACEHOLDER_START, MATH_PLACEHOLDER_END)).unwrap();
+    let id_pattern = Regex::new(r#"\sid="([^"]*)""#).unwrap();
+    let mut used: std::collections::HashSet<String> = heading_pattern.captures_iter(html)
+        .filter_map(|caps| caps.get(2).and_then(|attrs| id_pattern.captures(attrs.as_str())))
+        .map(|caps| caps[1].to_string())
+        .collect();
+    heading_pattern.replace_all(html, |caps: &regex::Captures| {
+        let attrs = caps.get(2).map(|m| m.as_str()).unwrap_or("");
+        if id_pattern.is_match(attrs) {
+            return caps[0].to_string();
+        }
+        let base = slugify(&unescape_html(&tag_pattern.replace_all(&caps[3], "")));
+        let mut id = base.clone();
+        let mut n = 0;
+        while !used.insert(id.clone()) {
+            n += 1;
+            id = format!("{}-{}", base, n);
+        }
+        format!("<h{} id=\"{}\"{}>{}</h{}>", &caps[1], escape_html(&id), attrs, &caps[3], &caps[1])
+    }).into_owned()
+}
+
+fn render_toc(html: &str, min_level: u8, max_level: u8) -> String {
+    // Links to the headings in [min_level, max_level], which all have ids from add_heading_ids;
+    // levels are shown by indentation (toc-level-N) rather than nested lists
+    let heading_pattern = Regex::new(r#"(?s)<h([1-6]) id="([^"]*)"[^>]*>(.*?)</h[1-6]>"#).unwrap();
+    let tag_pattern = Regex::new(r"<[^>]*>").unwrap();
+    let mut items = String::new();
+    for caps in heading_pattern.captures_iter(html) {
+        let level: u8 = caps[1].parse().unwrap_or(1);
+        if level < min_level || level > max_level {
+            continue;
+        }
+        items.push_str(&format!(
+            "<li class=\"toc-level-{}\"><a href=\"#{}\">{}</a></li>\n",
+            level, &caps[2], tag_pattern.replace_all(&caps[3], "").trim()
+        ));
+    }
+    if items.is_empty() {
+        return String::new();
+    }
+    format!("<nav class=\"toc\" aria-label=\"Table of contents\">\n<ul>\n{}</ul>\n</nav>\n", items)
+}
+
+fn render_image_captions(html: &str) -> String {
+    // An image alone in its paragraph with title text becomes a captioned figure;
+    // inline images and images without a title are left as they are
+    let image_pattern = Regex::new(r#"<p>(<img [^>]*?title="([^"]*)"[^>]*>)</p>"#).unwrap();
+    image_pattern.replace_all(html, |caps: &regex::Captures| {
+        format!("<figure>\n{}\n<figcaption>{}</figcaption>\n</figure>", &caps[1], &caps[2])
+    }).into_owned()
+}
+
+fn render_mermaid_blocks(html: &str) -> String {
+    // ```mermaid fences become <pre class="mermaid"> so mermaid.js renders them client-side
+    let mermaid_pattern = Regex::new(r#"(?s)<pre><code class="language-mermaid">(.*?)</code></pre>"#).unwrap();
+    mermaid_pattern.replace_all(html, r#"<pre class="mermaid">$1</pre>"#).into_owned()
+}
+
+fn add_line_numbers(html: &str, numbered_blocks: &[bool]) -> String {
+    // The gutter is a separate aria-hidden element outside <code>, so selecting or copying
+    // the code never picks up the numbers and highlight.js leaves it alone
+    let code_block_pattern = Regex::new(r#"(?s)<pre><code([^>]*)>(.*?)</code></pre>"#).unwrap();
+    let mut index = 0;
+    code_block_pattern.replace_all(html, |caps: &regex::Captures| {
+        let numbered = numbered_blocks.get(index).copied().unwrap_or(false);
+        index += 1;
+        if !numbered || ["language-mermaid", "language-dot", "language-graphviz"].iter().any(|lang| caps[1].contains(lang)) {
+            return caps[0].to_string();
+        }
+        let line_count = caps[2].trim_end_matches('\n').split('\n').count();
+        let rows: Vec<String> = (1..=line_count).map(|n| n.to_string()).collect();
+        format!(
+            "<pre class=\"line-numbers\"><span class=\"line-numbers-rows\" aria-hidden=\"true\">{}</span><code{}>{}</code></pre>",
+            rows.join("\n"), &caps[1], &caps[2]
+        )
+    }).into_owned()
+}
+
+fn unescape_html(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&gt;", ">")
+        .replace("&lt;", "<")
+        .replace("&amp;", "&")
+}
+
+// The missing-`dot` warning is printed once per build, not once per diagram
+static DOT_MISSING_WARNED: AtomicBool = AtomicBool::new(false);
+
+fn run_dot(dot: &str, source: &str) -> std::io::Result<Result<String, String>> {
+    // Outer error: dot couldn't be run at all. Inner error: dot rejected the diagram.
+    use std::io::Write;
+    let mut child = std::process::Command::new(dot)
+        .arg("-Tsvg")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(source.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Ok(Err(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+    let svg = String::from_utf8_lossy(&output.stdout);
+    // Drop the XML prolog and doctype so the SVG can sit inline in the page
+    Ok(Ok(svg.find("<svg").map(|start| svg[start..].trim_end().to_string()).unwrap_or_default()))
+}
+
+fn render_graphviz_blocks(html: &str, dot: &str) -> String {
+    // ```dot / ```graphviz fences become inline SVG at build time. If dot can't run,
+    // the source stays a code block; syntax errors are reported per diagram.
+    let dot_pattern = Regex::new(r#"(?s)<pre><code class="language-(?:dot|graphviz)">(.*?)</code></pre>"#).unwrap();
+    dot_pattern.replace_all(html, |caps: &regex::Captures| {
+        if DOT_MISSING_WARNED.load(Ordering::Relaxed) {
+            return caps[0].to_string();
+        }
+        match run_dot(dot, &unescape_html(&caps[1])) {
+            Ok(Ok(svg)) => format!("<div class=\"graphviz\">{}</div>", svg),
+            Ok(Err(e)) => {
+                log_warn!("Graphviz failed to render a diagram: {}", e);
+                caps[0].to_string()
+            }
+            Err(e) => {
+                if !DOT_MISSING_WARNED.swap(true, Ordering::Relaxed) {
+                    log_warn!("Graphviz '{}' could not be run ({}); dot diagrams are shown as code", dot, e);
+                }
+                caps[0].to_string()
+            }
+        }
+    }).into_owned()
+}
+
+fn add_copy_buttons(html: &str) -> String {
+    // Only fenced/indented code blocks get a button, never inline <code>
+    let code_block_pattern = Regex::new(
+        r#"(?s)<pre[^>]*>(?:<span class="line-numbers-rows"[^>]*>[^<]*</span>)?<code[^>]*>.*?</code></pre>"#
+    ).unwrap();
+    code_block_pattern.replace_all(html, |caps: &regex::Captures| {
+        format!(
+            "<div class=\"code-block\"><button type=\"button\" class=\"copy-button\" aria-label=\"Copy code to clipboard\">Copy</button>{}</div>",
+            &caps[0]
+        )
+    }).into_owned()
+}
+
+const COPY_BUTTON_SCRIPT: &str = r#"
+    <script>
+    document.addEventListener('click', function(event) {
+        var button = event.target.closest('.copy-button');
+        if (!button) return;
+        var code = button.parentElement.querySelector('code');
+        navigator.clipboard.writeText(code.innerText).then(function() {
+            button.textContent = 'Copied!';
+            setTimeout(function() { button.textContent = 'Copy'; }, 2000);
+        });
+    });
+    </script>"#;
+
+fn render_definition_lists(html: &str) -> String {
+    // pulldown-cmark 0.9 has no definition lists, so turn paragraphs shaped like
+    // "Term\n: Definition" into <dl> blocks after rendering
+    let paragraph_pattern = Regex::new(r"(?s)<p>(.*?)</p>").unwrap();
+    let converted = paragraph_pattern.replace_all(html, |caps: &regex::Captures| {
+        let lines: Vec<&str> = caps[1].lines().collect();
+        let is_definition = lines.len() >= 2
+            && !lines[0].starts_with(": ")
+            && lines[1..].iter().all(|line| line.starts_with(": "));
+        if !is_definition {
+            return caps[0].to_string();
+        }
+        let mut dl = format!("<dl>\n<dt>{}</dt>\n", lines[0]);
+        for line in &lines[1..] {
+            dl.push_str(&format!("<dd>{}</dd>\n", line[2..].trim()));
+        }
+        dl.push_str("</dl>");
+        dl
+    });
+    // Consecutive terms become one list
+    converted.replace("</dl>\n<dl>\n", "")
+}
+
+fn markdown_to_html(markdown: &str, markdown_files: &std::collections::HashMap<String, String>, math: &mut MathRenderer, config: &Config, directives: &Directives, page_path: &Path, base_path: &str) -> String {
+    let (markdown, abbreviations) = if config.abbreviations == Some(true) {
+        extract_abbreviations(markdown)
+    } else {
+        (markdown.to_string(), Vec::new())
+    };
+
     // Pre-process math expressions: render them server-side with KaTeX
-    let processed_markdown = preprocess_math(markdown);
+    let processed_markdown = if directives.no_math {
+        markdown
+    } else {
+        timed(Phase::Math, || preprocess_math(&markdown, math))
+    };
     
-    let options = Options::all();
-    let parser = Parser::new_ext(&processed_markdown, options);
+    let options = config.markdown.parser_options();
+    let emoji = config.emoji == Some(true);
+    let inline_extensions = InlineExtensions::new(&abbreviations);
+    let line_numbers = config.line_numbers == Some(true);
+    let mut numbered_blocks: Vec<bool> = Vec::new();  // One entry per code block, in order
+    let mut in_code_block = false;
+    let parser = Parser::new_ext(&processed_markdown, options).map(|event| {
+        // Code spans are separate events; code block text is tracked so it stays literal
+        use pulldown_cmark::{CodeBlockKind, Event, Tag};
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(ref info))) => {
+                in_code_block = true;
+                // ```python {linenos} numbers a single block even when line_numbers is off
+                numbered_blocks.push(line_numbers || info.contains("{linenos}"));
+                let info = info.replace("{linenos}", "");
+                let info = info.trim();
+                // Map author shorthands (```sh) to the language highlight.js knows (bash)
+                let (lang, rest) = info.split_once(' ').unwrap_or((info, ""));
+                let lang = config.highlight_language_aliases.as_ref()
+                    .and_then(|a| a.get(lang))
+                    .map(String::as_str)
+                    .unwrap_or(lang);
+                let info = if rest.is_empty() { lang.to_string() } else { format!("{} {}", lang, rest) };
+                return Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info.into())));
+            }
+            Event::Start(Tag::CodeBlock(_)) => {
+                in_code_block = true;
+                numbered_blocks.push(line_numbers);
+            }
+            Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+            Event::Text(ref text) if !in_code_block => {
+                let text = if emoji { replace_emoji_shortcodes(text) } else { text.to_string() };
+                return match inline_extensions.render(&text) {
+                    Some(html) => Event::Html(html.into()),
+                    None => Event::Text(text.into()),
+                };
+            }
+            _ => {}
+        }
+        event
+    });
     let mut html_output = String::new();
-    html::push_html(&mut html_output, parser);
+    timed(Phase::Markdown, || html::push_html(&mut html_output, parser));
+    let mut html_output = add_heading_ids(&html_output);
+    if config.toc == Some(true) {
+        html_output = render_toc(&html_output, config.toc_min.unwrap_or(1), config.toc_depth.unwrap_or(3)) + &html_output;
+    }
+    let mut html_output = math.restore(&html_output);
+    if numbered_blocks.contains(&true) {
+        html_output = add_line_numbers(&html_output, &numbered_blocks);
+    }
+    if html_output.contains("<code class=\"language-dot\">") || html_output.contains("<code class=\"language-graphviz\">") {
+        html_output = render_graphviz_blocks(&html_output, config.graphviz.as_deref().unwrap_or("dot"));
+    }
+    let html_output = render_definition_lists(&style_task_lists(&html_output));
+    let html_output = render_image_captions(&html_output);
+    let mut html_output = render_mermaid_blocks(&render_admonitions(&html_output));
+    if config.code_copy == Some(true) {
+        html_output = add_copy_buttons(&html_output);
+    }
+    if config.external_links_new_tab != Some(false) {
+        html_output = mark_external_links(&html_output);
+    }
     
-    convert_internal_links(&html_output, markdown_files)
+    timed(Phase::Links, || convert_internal_links(&html_output, markdown_files, base_path, page_path))
+}
+
+fn excerpt(markdown: &str, max_len: usize, config: &Config) -> String {
+    // Plain-text summary of the prose, skipping code blocks, raw HTML and alert markers
+    use pulldown_cmark::{Event, Tag};
+    static ALERT_MARKER: std::sync::OnceLock<Regex> = std::sync::OnceLock::new();
+    let alert_marker = ALERT_MARKER.get_or_init(|| {
+        Regex::new(r"^\s*\[!(NOTE|TIP|IMPORTANT|WARNING|CAUTION|DANGER)\]").unwrap()
+    });
+    // Abbreviation definitions are taken out of the page, so they aren't prose either
+    let markdown = if config.abbreviations == Some(true) {
+        extract_abbreviations(markdown).0
+    } else {
+        markdown.to_string()
+    };
+    let emoji = config.emoji == Some(true);
+    let mut text = String::new();
+    let mut in_code_block = false;
+    let mut quote_start = None;  // Where the text of a blockquote's first paragraph begins
+    for event in Parser::new_ext(&markdown, config.markdown.parser_options()) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+            Event::Text(t) if emoji && !in_code_block => text.push_str(&replace_emoji_shortcodes(&t)),
+            Event::Text(t) | Event::Code(t) if !in_code_block => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            Event::Start(Tag::BlockQuote) => quote_start = Some(text.len()),
+            Event::End(Tag::Paragraph) => {
+                // A [!NOTE] marker becomes the admonition title on the page, not prose
+                if let Some(start) = quote_start.take() {
+                    if let Some(marker) = alert_marker.find(&text[start..]) {
+                        text.replace_range(start..start + marker.end(), "");
+                    }
+                }
+                text.push(' ');
+            }
+            Event::End(Tag::Heading(..) | Tag::Item | Tag::TableCell | Tag::BlockQuote) => text.push(' '),
+            _ => {}
+        }
+    }
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let mut result = String::new();
+    for word in words {
+        if !result.is_empty() && result.len() + word.len() + 1 > max_len {
+            result.push('…');
+            return result;
+        }
+        if !result.is_empty() {
+            result.push(' ');
+        }
+        result.push_str(word);
+    }
+    result
+}
+
+fn word_count(markdown: &str, config: &Config) -> usize {
+    // Words of prose only: math spans and code blocks don't count towards reading time
+    use pulldown_cmark::{Event, Tag};
+    static MATH_PATTERN: std::sync::OnceLock<Regex> = std::sync::OnceLock::new();
+    let math_pattern = MATH_PATTERN.get_or_init(|| Regex::new(r"(?s)\$\$.*?\$\$|\$[^$\n]+\$").unwrap());
+    let prose = math_pattern.replace_all(markdown, " ");
+    let mut count = 0;
+    let mut in_code_block = false;
+    for event in Parser::new_ext(&prose, config.markdown.parser_options()) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+            Event::Text(t) | Event::Code(t) if !in_code_block => count += t.split_whitespace().count(),
+            _ => {}
+        }
+    }
+    count
+}
+
+fn reading_time_minutes(words: usize) -> usize {
+    // Roughly 200 words per minute, never less than a minute
+    words.div_ceil(200).max(1)
 }
 
+// Opens and closes the navbar list on small screens
+const NAV_TOGGLE_SCRIPT: &str = r#"<script>
+(function() {
+    var nav = document.currentScript.parentElement;
+    var toggle = nav.querySelector('.nav-toggle');
+    var list = document.getElementById('site-nav-list');
+    nav.classList.add('nav-js');
+    toggle.addEventListener('click', function() {
+        var open = list.classList.toggle('nav-open');
+        toggle.setAttribute('aria-expanded', open ? 'true' : 'false');
+    });
+})();
+</script>
+"#;
+
 #[derive(Clone)]
 enum NavbarItem {
     MarkdownFile(PathBuf, String),  // (path, title)
     ExternalLink(String, String),   // (url, text)
     Dropdown(String),                // (dropdown name)
+    Separator,                       // {separator: true} in navbar_order
+    Label(String),                   // Non-clickable group label, {label: "Docs"}
+    Conditional(String, Box<NavbarItem>),  // Only on pages matching show_on, e.g. "math/"
 }
 
 fn generate_navbar(
     navbar_items: &[NavbarItem], 
-    output_in_dist: bool,
+    _output_in_dist: bool,
     dropdowns: Option<&std::collections::HashMap<String, serde_yaml::Value>>,
     markdown_titles: &std::collections::HashMap<String, String>,
     current_page: Option<&str>,
     asset_prefix: &str,
+    logo: Option<&str>,
 ) -> String {
     let mut nav = String::from("<nav style=\"background: #000; padding: 10px; margin-bottom: 20px; border-bottom: 2px solid #333;\">\n");
     nav.push_str("<style>
@@ -359,8 +1998,32 @@ fn generate_navbar(
     color: #8C6D2C !important;
     text-decoration: none;
 }
+.nav-toggle {
+    display: none;
+    background: none;
+    border: 1px solid #444;
+    border-radius: 4px;
+    color: #fff;
+    font-size: 1.5rem;
+    line-height: 1;
+    padding: 5px 10px;
+    cursor: pointer;
+}
+@media screen and (max-width: 768px) {
+    nav.nav-js .nav-toggle {
+        display: block;
+    }
+    nav.nav-js #site-nav-list:not(.nav-open) {
+        display: none !important;
+    }
+    nav.nav-js #site-nav-list {
+        margin-top: 10px !important;
+    }
+}
 </style>\n");
-    nav.push_str("<ul style=\"list-style: none; margin: 0; padding: 0; display: flex; gap: 20px; align-items: center; font-size: 1.25rem;\">\n");
+    // Hamburger toggle for small screens; without JS the list simply stays visible
+    nav.push_str("<button class=\"nav-toggle\" type=\"button\" aria-controls=\"site-nav-list\" aria-expanded=\"false\" aria-label=\"Toggle navigation\">&#9776;</button>\n");
+    nav.push_str("<ul id=\"site-nav-list\" style=\"list-style: none; margin: 0; padding: 0; display: flex; gap: 20px; align-items: center; font-size: 1.25rem;\">\n");
     
     // Always add logo/IDEEP link at the start
     let index_title = markdown_titles.get("index")
@@ -374,13 +2037,29 @@ fn generate_navbar(
     } else {
         format!("{}index.html", asset_prefix)
     };
+    let logo_img = logo
+        .map(|src| format!(
+            "<img src=\"{}\" alt=\"Logo\" style=\"height: 40px; width: auto;\">",
+            resolve_page_url(src, asset_prefix)
+        ))
+        .unwrap_or_default();
     nav.push_str(&format!(
-        "  <li><a href=\"{}\" class=\"{}\" style=\"display: flex; align-items: center; gap: 10px;\"><img src=\"{}assets/logo-wide.png\" alt=\"Logo\" style=\"height: 40px; width: auto;\">{}</a></li>\n",
-        index_path, index_link_class, asset_prefix, index_title
+        "  <li><a href=\"{}\" class=\"{}\" style=\"display: flex; align-items: center; gap: 10px;\">{}{}</a></li>\n",
+        index_path, index_link_class, logo_img, index_title
     ));
     
     for item in navbar_items {
+        let item = match item {
+            NavbarItem::Conditional(show_on, inner) => {
+                if !current_page.is_some_and(|page| location_matches(show_on, page)) {
+                    continue;
+                }
+                inner.as_ref()
+            }
+            item => item,
+        };
         match item {
+            NavbarItem::Conditional(..) => {}
             NavbarItem::MarkdownFile(relative_path, title) => {
                 // Convert relative path to HTML path (e.g., "math/sir.md" -> "math/sir.html")
                 let html_path_base = relative_path.with_extension("html")
@@ -396,7 +2075,7 @@ fn generate_navbar(
                     continue;
                 }
                 
-                let is_active = current_page.map(|cp| cp == &rel_key || cp == relative_path.file_stem().and_then(|s| s.to_str()).unwrap_or("")).unwrap_or(false);
+                let is_active = current_page.map(|cp| cp == rel_key || cp == relative_path.file_stem().and_then(|s| s.to_str()).unwrap_or("")).unwrap_or(false);
                 let link_class = if is_active { "nav-link active" } else { "nav-link" };
                 
                 nav.push_str(&format!(
@@ -410,6 +2089,15 @@ fn generate_navbar(
                     url, text
                 ));
             }
+            NavbarItem::Separator => {
+                nav.push_str("  <li class=\"nav-separator\" role=\"separator\" style=\"width: 1px; align-self: stretch; background: #555;\"></li>\n");
+            }
+            NavbarItem::Label(text) => {
+                nav.push_str(&format!(
+                    "  <li class=\"nav-label\" style=\"color: #aaa; font-family: Arial, sans-serif; font-size: 0.9rem; text-transform: uppercase;\">{}</li>\n",
+                    escape_html(text)
+                ));
+            }
             NavbarItem::Dropdown(dropdown_name) => {
                 // Render dropdown inline
                 if let Some(dropdowns_map) = dropdowns {
@@ -469,10 +2157,10 @@ fn generate_navbar(
                                         }
                                         serde_yaml::Value::Mapping(map) => {
                                             // Object with url and text fields
-                                            let url = map.get(&serde_yaml::Value::String("url".to_string()))
+                                            let url = map.get(serde_yaml::Value::String("url".to_string()))
                                                 .and_then(|v| v.as_str())
                                                 .unwrap_or("");
-                                            let text = map.get(&serde_yaml::Value::String("text".to_string()))
+                                            let text = map.get(serde_yaml::Value::String("text".to_string()))
                                                 .and_then(|v| v.as_str())
                                                 .unwrap_or("");
                                             if !url.is_empty() && !text.is_empty() {
@@ -497,33 +2185,363 @@ fn generate_navbar(
         }
     }
     
-    nav.push_str("</ul>\n</nav>\n");
+    nav.push_str("</ul>\n");
+    nav.push_str(NAV_TOGGLE_SCRIPT);
+    nav.push_str("</nav>\n");
     nav
 }
 
-fn generate_html(title: &str, content: &str, navbar: &str, asset_prefix: &str) -> Result<String, Box<dyn std::error::Error>> {
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn format_byline(authors: &[String]) -> String {
+    let names: Vec<String> = authors.iter().map(|a| escape_html(a)).collect();
+    let joined = match names.len() {
+        0 => return String::new(),
+        1 => names[0].clone(),
+        2 => format!("{} and {}", names[0], names[1]),
+        n => format!("{}, and {}", names[..n - 1].join(", "), names[n - 1]),
+    };
+    format!("<p class=\"byline\">By {}</p>\n            ", joined)
+}
+
+fn is_external_url(url: &str) -> bool {
+    url.contains("://") || url.starts_with("//") || url.starts_with('/') || url.starts_with("data:")
+}
+
+fn resolve_page_url(url: &str, asset_prefix: &str) -> String {
+    // Relative paths are resolved from the site root, external URLs pass through unchanged
+    if is_external_url(url) {
+        url.to_string()
+    } else {
+        format!("{}{}", asset_prefix, url)
+    }
+}
+
+fn render_template(template: &str, values: &[(&str, &str)]) -> String {
+    // Substitute {{name}} placeholders; unknown placeholders are left as-is
+    let placeholder_pattern = Regex::new(r"\{\{\s*([a-zA-Z_]+)\s*\}\}").unwrap();
+    placeholder_pattern.replace_all(template, |caps: &regex::Captures| {
+        let name = &caps[1];
+        values.iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.to_string())
+            .unwrap_or_else(|| caps[0].to_string())
+    }).into_owned()
+}
+
+// Everything generate_html needs to know about the page being rendered
+struct PageContext<'a> {
+    title: &'a str,
+    content: &'a str,              // Rendered body HTML
+    navbar: &'a str,
+    asset_prefix: &'a str,
+    page_path: &'a str,            // Output path from the site root, e.g. "math/sir.html"
+    frontmatter: &'a FrontMatter,
+    reading_time: Option<usize>,   // Minutes, when reading_time is enabled
+    last_updated: Option<&'a str>,  // YYYY-MM-DD, when last_updated is enabled
+    prev: Option<(&'a str, &'a str)>,  // (title, href) of the previous page in page_order
+    next: Option<(&'a str, &'a str)>,
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // Days since 1970-01-01 to (year, month, day), after Howard Hinnant's algorithm
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn last_updated_date(path: &Path) -> Option<String> {
+    // The last commit touching the file, else its modification time (git missing, untracked file)
+    let committed = std::process::Command::new("git")
+        .args(["log", "-1", "--format=%cI", "--"])
+        .arg(path)
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|date| date.trim().get(..10).map(str::to_string));
+    committed.or_else(|| {
+        let secs = fs::metadata(path).ok()?
+            .modified().ok()?
+            .duration_since(std::time::UNIX_EPOCH).ok()?
+            .as_secs() as i64;
+        let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+        Some(format!("{:04}-{:02}-{:02}", year, month, day))
+    })
+}
+
+fn current_year() -> i64 {
+    today().0
+}
+
+fn today() -> (i64, u32, u32) {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    civil_from_days(secs.div_euclid(86_400))
+}
+
+fn minify_page(html: &str) -> String {
+    // <pre> contents are preserved by the minifier; inline scripts are left untouched
+    let mut cfg = minify_html::Cfg::spec_compliant();
+    cfg.do_not_minify_doctype = true;
+    cfg.keep_closing_tags = true;
+    cfg.keep_html_and_head_opening_tags = true;
+    cfg.minify_css = true;
+    cfg.minify_js = false;
+    String::from_utf8_lossy(&minify_html::minify(html.as_bytes(), &cfg)).into_owned()
+}
+
+fn generate_html(page: &PageContext, config: &Config) -> Result<String, Box<dyn std::error::Error>> {
+    let PageContext { title, content, navbar, asset_prefix, page_path, frontmatter, reading_time, last_updated, prev, next } = *page;
     let katex_css = format!(r#"<link rel="stylesheet" href="{}assets/vendor/katex/katex.min.css" type="text/css" />"#, asset_prefix);
 
-    // Read footer.html
-    let footer_path = Path::new("assets/footer.html");
+    // Canonical URL: explicit front matter wins, otherwise site_url + the page's path
+    let mut page_head = String::new();
+    let canonical = frontmatter.canonical.clone().or_else(|| {
+        config.site_url.as_ref()
+            .map(|site_url| format!("{}/{}", site_url.trim_end_matches('/'), page_path))
+    });
+    if let Some(ref canonical) = canonical {
+        page_head.push_str(&format!("\n    <link rel=\"canonical\" href=\"{}\">", escape_html(canonical)));
+    }
+    if frontmatter.noindex == Some(true) {
+        page_head.push_str("\n    <meta name=\"robots\" content=\"noindex, nofollow\">");
+    }
+    if let Some(ref theme_color) = config.theme_color {
+        page_head.push_str(&format!("\n    <meta name=\"theme-color\" content=\"{}\">", escape_html(theme_color.trim())));
+    }
+
+    // schema.org Article data for dated pages only
+    if let Some(ref date) = frontmatter.date {
+        let mut article = serde_json::json!({
+            "@context": "https://schema.org",
+            "@type": "Article",
+            "headline": title,
+            "datePublished": date,
+        });
+        let authors: Vec<serde_json::Value> = frontmatter.all_authors().into_iter()
+            .map(|name| serde_json::json!({ "@type": "Person", "name": name }))
+            .collect();
+        if !authors.is_empty() {
+            article["author"] = serde_json::Value::Array(authors);
+        }
+        if let Some(ref url) = canonical {
+            article["url"] = serde_json::Value::String(url.clone());
+        }
+        // "</" inside a string would otherwise close the script element early
+        let json = serde_json::to_string(&article)?.replace("</", "<\\/");
+        page_head.push_str(&format!("\n    <script type=\"application/ld+json\">{}</script>", json));
+    }
+
+    // Per-page stylesheets and scripts from front matter
+    for css in frontmatter.css.iter().flatten() {
+        page_head.push_str(&format!(
+            "\n    <link rel=\"stylesheet\" href=\"{}\" type=\"text/css\" />",
+            resolve_page_url(css, asset_prefix)
+        ));
+    }
+    for script in frontmatter.scripts.iter().flatten() {
+        page_head.push_str(&format!("\n    {}", script.to_tag(asset_prefix)));
+    }
+
+    // A cover image leads the page, above any title
+    let mut byline = String::new();
+    if let Some(ref cover) = frontmatter.cover {
+        byline.push_str(&format!(
+            "<img class=\"page-cover\" src=\"{}\" alt=\"\">\n            ",
+            escape_html(&resolve_page_url(cover.trim(), asset_prefix))
+        ));
+    }
+    // The title is only in <title> unless a page asks for it as an on-page <h1>;
+    // by default the markdown is expected to provide its own heading
+    if frontmatter.title_heading.or(config.title_heading) == Some(true) {
+        byline.push_str(&format!("<h1 class=\"page-title\">{}</h1>\n            ", escape_html(title)));
+    }
+    byline.push_str(&format_byline(&frontmatter.all_authors()));
+    if let Some(minutes) = reading_time {
+        byline.push_str(&format!("<p class=\"reading-time\">{} min read</p>\n            ", minutes));
+    }
+    if let Some(date) = last_updated {
+        byline.push_str(&format!(
+            "<p class=\"last-updated\">Last updated <time datetime=\"{0}\">{0}</time></p>\n            ",
+            date
+        ));
+    }
+    let lang = escape_html(
+        frontmatter.lang.as_deref()
+            .or(config.default_lang.as_deref())
+            .unwrap_or("en")
+    );
+
+    if content.contains("class=\"copy-button\"") {
+        page_head.push_str(COPY_BUTTON_SCRIPT);
+    }
+
+    // Only pages with diagrams load mermaid, preferring a self-hosted copy
+    if content.contains("<pre class=\"mermaid\">") {
+        let mermaid_src = if Path::new("assets/vendor/mermaid/mermaid.min.js").exists() {
+            format!("{}assets/vendor/mermaid/mermaid.min.js", asset_prefix)
+        } else {
+            "https://cdn.jsdelivr.net/npm/mermaid@10/dist/mermaid.min.js".to_string()
+        };
+        page_head.push_str(&format!(
+            "\n    <script src=\"{}\"></script>\n    <script>mermaid.initialize({{ startOnLoad: true }});</script>",
+            mermaid_src
+        ));
+    }
+
+    // Favicon is omitted entirely when the file doesn't exist
+    let favicon_tag = resolve_site_image(config.favicon.as_ref(), "assets/logo.png")
+        .map(|icon| {
+            let mime = match Path::new(&icon).extension().and_then(|e| e.to_str()) {
+                Some("svg") => "image/svg+xml",
+                Some("ico") => "image/x-icon",
+                Some("jpg") | Some("jpeg") => "image/jpeg",
+                _ => "image/png",
+            };
+            format!(
+                "\n    <link rel=\"icon\" type=\"{}\" href=\"{}\" />",
+                mime, resolve_page_url(&icon, asset_prefix)
+            )
+        })
+        .unwrap_or_default();
+
+    // Read footer.html, filling in {{year}} and {{asset_prefix}} for links from nested pages.
+    // Pages under a top-level directory use assets/footers/<dir>.html when it exists.
+    let section_footer = page_path.split_once('/')
+        .map(|(section, _)| Path::new("assets/footers").join(format!("{}.html", section)))
+        .filter(|path| path.exists());
+    let footer_path = section_footer.unwrap_or_else(|| PathBuf::from("assets/footer.html"));
     let footer_content = if footer_path.exists() {
-        fs::read_to_string(footer_path)?
+        let year = current_year().to_string();
+        render_template(&fs::read_to_string(&footer_path)?, &[
+            ("year", &year),
+            ("asset_prefix", asset_prefix),
+        ])
     } else {
         String::new()
     };
 
-    Ok(format!(
-        r#"<!DOCTYPE html>
-<html lang="en">
+    // Previous/next links along the page_order sequence
+    let mut page_nav = String::new();
+    if prev.is_some() || next.is_some() {
+        page_nav.push_str("\n            <nav class=\"page-nav\">");
+        if let Some((prev_title, href)) = prev {
+            page_nav.push_str(&format!("<a class=\"page-nav-prev\" href=\"{}\">&larr; {}</a>", href, escape_html(prev_title)));
+        }
+        if let Some((next_title, href)) = next {
+            page_nav.push_str(&format!("<a class=\"page-nav-next\" href=\"{}\">{} &rarr;</a>", href, escape_html(next_title)));
+        }
+        page_nav.push_str("</nav>");
+    }
+
+    // Site-wide additions from config; extra_head is trusted raw HTML
+    let extra_head = config.extra_head.as_deref()
+        .map(|head| format!("\n    {}", head.trim()))
+        .unwrap_or_default();
+    let body_class = config.body_class.as_deref().map(escape_html).unwrap_or_default();
+    let viewport = escape_html(config.viewport.as_deref().unwrap_or("width=device-width, initial-scale=1.0"));
+
+    // <title>: "Page · Site" once a site_name is set, just the site name on the home page
+    let document_title = match config.site_name.as_deref() {
+        Some(site_name) if page_path == "index.html" => escape_html(site_name),
+        Some(site_name) => format!(
+            "{}{}{}",
+            escape_html(title),
+            escape_html(config.title_separator.as_deref().unwrap_or(" · ")),
+            escape_html(site_name)
+        ),
+        None => escape_html(title),
+    };
+    let body_class_attr = if body_class.is_empty() { String::new() } else { format!(" class=\"{}\"", body_class) };
+
+    // A page's named template wins over assets/template.html, which wins over the built-in layout
+    let named_template = frontmatter.template.as_deref().and_then(|name| {
+        let path = Path::new("assets/templates").join(format!("{}.html", name.trim()));
+        if !name.contains(['/', '\\']) && path.exists() {
+            Some(path)
+        } else {
+            log_warn!("Template '{}' not found at {}, using the default layout for {}", name, path.display(), page_path);
+            None
+        }
+    });
+    let template_path = named_template.unwrap_or_else(|| PathBuf::from("assets/template.html"));
+    if template_path.exists() {
+        let template = fs::read_to_string(&template_path)?;
+        let head = format!("{}{}{}", katex_css, page_head, extra_head);
+        return Ok(add_resource_hints(&render_template(&template, &[
+            ("title", title),
+            ("document_title", &document_title),
+            ("content", content),
+            ("navbar", navbar),
+            ("asset_prefix", asset_prefix),
+            ("head", &head),
+            ("footer", &footer_content),
+            ("byline", &byline),
+            ("lang", &lang),
+            ("page_nav", &page_nav),
+            ("body_class", &body_class),
+            ("viewport", &viewport),
+        ]), config.site_url.as_deref()));
+    }
+
+    // A bare theme name refers to the bundled highlight.js CDN styles
+    let highlight_theme = config.highlight_theme.as_deref().unwrap_or("default");
+    let highlight_css = if highlight_theme.ends_with(".css") {
+        resolve_page_url(highlight_theme, asset_prefix)
+    } else {
+        format!("https://cdnjs.cloudflare.com/ajax/libs/highlight.js/11.9.0/styles/{}.min.css", highlight_theme)
+    };
+
+    // FontAwesome: the default kit, another kit/script, or self-hosted CSS under assets/
+    let icons_tag = match config.icons_url.as_deref() {
+        _ if config.icons == Some(false) => String::new(),
+        Some(url) if url.ends_with(".css") => {
+            format!(r#"<link rel="stylesheet" href="{}" type="text/css" />"#, resolve_page_url(url, asset_prefix))
+        }
+        Some(url) => format!(r#"<script src="{}" crossorigin="anonymous"></script>"#, resolve_page_url(url, asset_prefix)),
+        None => r#"<script src="https://kit.fontawesome.com/1ffe760482.js" crossorigin="anonymous"></script>"#.to_string(),
+    };
+
+    // The inline <style> below and the navbar's own styles cover first paint, so with
+    // optimize_css the main stylesheet is preloaded and applied once it arrives
+    let stylesheet_href = format!("{}assets/styles.css", asset_prefix);
+    let stylesheet_tag = if config.optimize_css == Some(true) {
+        format!(
+            r#"<link rel="preload" href="{0}" as="style" onload="this.onload=null;this.rel='stylesheet'" />
+    <noscript><link rel="stylesheet" href="{0}" type="text/css" /></noscript>"#,
+            stylesheet_href
+        )
+    } else {
+        format!(r#"<link rel="stylesheet" href="{}" type="text/css" />"#, stylesheet_href)
+    };
+
+    Ok(add_resource_hints(&format!(
+        r##"<!DOCTYPE html>
+<html lang="{}">
 <head>
     <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>{}</title>
-    <link rel="icon" type="image/png" href="{}assets/logo.png" />
-    <link rel="stylesheet" href="{}assets/styles.css" type="text/css" />
-    <script src="https://kit.fontawesome.com/1ffe760482.js" crossorigin="anonymous"></script>
+    <meta name="viewport" content="{}">
+    <title>{}</title>{}
+    {}
+    {}
     <!-- Highlight.js for code syntax highlighting -->
-    <link rel="stylesheet" href="https://cdnjs.cloudflare.com/ajax/libs/highlight.js/11.9.0/styles/default.min.css">
+    <link rel="stylesheet" href="{}">
     <script src="https://cdnjs.cloudflare.com/ajax/libs/highlight.js/11.9.0/highlight.min.js"></script>
     <script src="https://cdnjs.cloudflare.com/ajax/libs/highlight.js/11.9.0/languages/bash.min.js"></script>
     <script src="https://cdnjs.cloudflare.com/ajax/libs/highlight.js/11.9.0/languages/julia.min.js"></script>
@@ -674,20 +2692,74 @@ fn generate_html(title: &str, content: &str, navbar: &str, asset_prefix: &str) -
         }}
     }}
     </style>
-    {}
+    {}{}{}
 </head>
-<body>
+<body{}>
+    <a class="skip-link" href="#content">Skip to content</a>
     {}
-    <div id="content">
+    <div id="content" tabindex="-1">
         <div class="blogbody">
-            {}
+            {}{}{}
         </div>
     </div>
     {}
 </body>
-</html>"#,
-        title, asset_prefix, asset_prefix, katex_css, navbar, content, footer_content
-    ))
+</html>"##,
+        lang, viewport, document_title, favicon_tag, stylesheet_tag, icons_tag, highlight_css, katex_css, page_head, extra_head, body_class_attr, navbar, byline, content, page_nav, footer_content
+    ), config.site_url.as_deref()))
+}
+
+fn add_resource_hints(html: &str, site_url: Option<&str>) -> String {
+    // Preconnect to each external origin the finished page loads something from
+    // (scripts, stylesheets, icons, images, frames); plain links and the site's own origin
+    // (assets under absolute_links) don't count
+    let own_origin = site_url.map(|url| {
+        let scheme_end = url.find("://").map(|i| i + 3).unwrap_or(0);
+        let end = url[scheme_end..].find('/').map(|i| scheme_end + i).unwrap_or(url.len());
+        url[..end].to_string()
+    });
+    let tag_pattern = Regex::new(r"<(script|link|img|iframe|source|video|audio)\b[^>]*>").unwrap();
+    let url_pattern = Regex::new(r#"\b(?:src|href)="((?:https?:)?//[^/"]+)"#).unwrap();
+    let mut origins: Vec<(String, bool)> = Vec::new();  // (origin, needs crossorigin)
+    for tag in tag_pattern.find_iter(html).map(|m| m.as_str()) {
+        if tag.starts_with("<link") && !["stylesheet", "preload", "icon", "modulepreload"].iter().any(|rel| tag.contains(&format!("rel=\"{}\"", rel))) {
+            continue;
+        }
+        let Some(caps) = url_pattern.captures(tag) else { continue };
+        let origin = if caps[1].starts_with("//") { format!("https:{}", &caps[1]) } else { caps[1].to_string() };
+        if own_origin.as_deref() == Some(origin.as_str()) {
+            continue;
+        }
+        let cors = tag.contains("crossorigin");
+        match origins.iter_mut().find(|(o, _)| *o == origin) {
+            Some(entry) => entry.1 |= cors,
+            None => origins.push((origin, cors)),
+        }
+    }
+    if origins.is_empty() {
+        return html.to_string();
+    }
+
+    let mut hints = String::new();
+    for (origin, cors) in &origins {
+        let crossorigin = if *cors { " crossorigin" } else { "" };
+        hints.push_str(&format!("\n    <link rel=\"preconnect\" href=\"{}\"{}>", origin, crossorigin));
+        hints.push_str(&format!("\n    <link rel=\"dns-prefetch\" href=\"{}\">", origin));
+    }
+    // Hints go right after <meta charset> (or <head>) so they're seen before anything they help
+    let anchor = Regex::new(r"(?i)<meta charset[^>]*>").unwrap().find(html)
+        .or_else(|| Regex::new(r"(?i)<head\b[^>]*>").unwrap().find(html));
+    match anchor {
+        Some(m) => format!("{}{}{}", &html[..m.end()], hints, &html[m.end()..]),
+        None => html.to_string(),
+    }
+}
+
+fn page_asset_prefix(relative_path: &Path, base_path: Option<&str>) -> String {
+    match base_path {
+        Some(base) => base.to_string(),
+        None => calculate_asset_prefix(relative_path),
+    }
 }
 
 fn calculate_asset_prefix(relative_path: &Path) -> String {
@@ -705,90 +2777,344 @@ fn calculate_asset_prefix(relative_path: &Path) -> String {
 }
 
 fn calculate_relative_link_path(from_path: &Path, to_path: &str) -> String {
-    // If to_path is "index", it's always at the root
-    if to_path == "index" {
-        let depth = from_path.parent()
-            .map(|p| p.components().count())
-            .unwrap_or(0);
-        if depth == 0 {
-            "index.html".to_string()
-        } else {
-            format!("{}index.html", "../".repeat(depth))
-        }
-    } else {
-        // For other paths, calculate relative path
-        let from_dir = from_path.parent().unwrap_or(Path::new(""));
-        let to_path_buf = Path::new(to_path);
-        
-        // If they're in the same directory
-        if from_dir == to_path_buf.parent().unwrap_or(Path::new("")) {
-            format!("{}.html", to_path)
-        } else {
-            // Need to go up to common ancestor, then down to target
-            let depth = from_dir.components().count();
-            if depth == 0 {
-                format!("{}.html", to_path)
-            } else {
-                format!("{}{}.html", "../".repeat(depth), to_path)
-            }
-        }
-    }
+    // Link from the page at `from_path` to the page key `to_path` (both relative to the
+    // site root): go up to the common ancestor directory, then down to the target
+    let from = from_path.to_string_lossy().replace('\\', "/");
+    let from_dirs: Vec<&str> = from.split('/').filter(|s| !s.is_empty()).collect();
+    let from_dirs = &from_dirs[..from_dirs.len().saturating_sub(1)];
+    let to_parts: Vec<&str> = to_path.split('/').filter(|s| !s.is_empty()).collect();
+    let to_dirs = &to_parts[..to_parts.len().saturating_sub(1)];
+
+    let common = from_dirs.iter().zip(to_dirs).take_while(|(a, b)| a == b).count();
+    format!("{}{}.html", "../".repeat(from_dirs.len() - common), to_parts[common..].join("/"))
 }
 
-fn copy_assets_to_dist() -> Result<(), Box<dyn std::error::Error>> {
-    let assets_dir = Path::new("assets");
-    let dist_assets_dir = Path::new("dist/assets");
-    
-    // Create dist/assets directory if it doesn't exist
-    if !dist_assets_dir.exists() {
-        fs::create_dir_all(dist_assets_dir)?;
-    }
-    
-    // Recursively copy all files and directories from assets to dist/assets
-    if assets_dir.exists() {
-        copy_directory_recursive(assets_dir, dist_assets_dir)?;
+fn section_title(dir: &str) -> String {
+    // "data-analysis" -> "Data analysis"
+    let name = dir.rsplit('/').next().unwrap_or(dir).replace(['-', '_'], " ");
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => name,
     }
-    
-    Ok(())
 }
 
-fn copy_directory_recursive(src: &Path, dst: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
-        let path = entry.path();
-        let file_name = path.file_name().unwrap();
-        let dest_path = dst.join(file_name);
-        
-        if path.is_dir() {
-            // Create destination directory and recurse
-            fs::create_dir_all(&dest_path)?;
-            copy_directory_recursive(&path, &dest_path)?;
-        } else {
-            // Copy file
-            fs::copy(&path, &dest_path)?;
-            println!("Copied: {} -> {}", path.display(), dest_path.display());
+fn render_section_index(title: &str, entries: &[&SearchEntry], link_prefix: &str) -> String {
+    // Entries are siblings of the first index page, so links need the file name plus
+    // link_prefix ("../../" from dir/page/N/index.html)
+    let mut html = format!("<h1>{}</h1>\n<ul class=\"section-index\">\n", escape_html(title));
+    for entry in entries {
+        let file_name = entry.url.rsplit('/').next().unwrap_or(&entry.url);
+        html.push_str(&format!("<li><a href=\"{}{}\">{}</a>", link_prefix, file_name, escape_html(&entry.title)));
+        if !entry.excerpt.is_empty() {
+            html.push_str(&format!("<p>{}</p>", escape_html(&entry.excerpt)));
         }
+        html.push_str("</li>\n");
     }
-    
-    Ok(())
+    html.push_str("</ul>\n");
+    html
 }
 
-fn find_markdown_files(dir: &Path, base_dir: &Path, files: &mut Vec<(PathBuf, PathBuf, String)>) -> Result<(), Box<dyn std::error::Error>> {
-    if !dir.exists() {
-        return Ok(());
-    }
+fn generate_redirect_html(target: &str, title: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <title>Redirecting to {title}</title>
+    <link rel="canonical" href="{target}">
+    <meta http-equiv="refresh" content="0; url={target}">
+</head>
+<body>
+    <p>This page has moved to <a href="{target}">{title}</a>.</p>
+</body>
+</html>"#,
+        title = escape_html(title),
+        target = target
+    )
+}
+
+fn normalize_relative_path(path: &Path) -> Option<PathBuf> {
+    // Resolve "." and ".." lexically; None if the path escapes its root
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::Normal(part) => normalized.push(part),
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            _ => return None,
+        }
+    }
+    Some(normalized)
+}
+
+// Images referenced relative to a page (e.g. `![](diagram.png)` in content/math/sir.md) are
+// copied to dist/assets/<page dir>/ and their src rewritten through the asset prefix.
+// copy_assets_to_dist runs afterwards, so a file at the same path under assets/ wins.
+fn rewrite_page_images(
+    html: &str,
+    page_dir: &Path,
+    content_dir: &Path,
+    dist_dir: &Path,
+    asset_prefix: &str,
+) -> Result<(String, usize), Box<dyn std::error::Error>> {
+    let img_pattern = Regex::new(r#"<img([^>]*?)\ssrc="([^"]+)""#).unwrap();
+    let mut copied = 0;
+    let mut result = String::with_capacity(html.len());
+    let mut last_end = 0;
+    for cap in img_pattern.captures_iter(html) {
+        let full_match = cap.get(0).unwrap();
+        let src = &cap[2];
+        if is_external_url(src) {
+            continue;
+        }
+        let Some(relative) = normalize_relative_path(&page_dir.join(src)) else {
+            continue;
+        };
+        let source = content_dir.join(&relative);
+        if !source.is_file() {
+            // Not colocated with the page (e.g. a root-relative "assets/..." path)
+            continue;
+        }
+        let dest = dist_dir.join("assets").join(&relative);
+        copy_output(&source, &dest)?;
+        copied += 1;
+        let new_src = format!("{}assets/{}", asset_prefix, relative.to_string_lossy().replace('\\', "/"));
+        result.push_str(&html[last_end..full_match.start()]);
+        result.push_str(&format!(r#"<img{} src="{}""#, &cap[1], new_src));
+        last_end = full_match.end();
+    }
+    result.push_str(&html[last_end..]);
+    Ok((result, copied))
+}
+
+// Relative src/href values pointing at a file colocated under the content root (e.g. `image.png`
+// from content/post/a.md) are rewritten through the asset prefix so they resolve from any page
+// location, including 404.html and base_path deployments.
+fn rewrite_colocated_refs(
+    html: &str,
+    page_dir: &Path,
+    colocated: &std::collections::HashSet<String>,
+    asset_prefix: &str,
+) -> String {
+    let attr_pattern = Regex::new(r##"(\s(?:src|href))="([^"#?]+)([^"]*)""##).unwrap();
+    attr_pattern.replace_all(html, |caps: &regex::Captures| {
+        let path = &caps[2];
+        if is_external_url(path) || path.contains(':') {
+            return caps[0].to_string();
+        }
+        let key = normalize_relative_path(&page_dir.join(path))
+            .map(|p| p.to_string_lossy().replace('\\', "/"));
+        match key {
+            Some(key) if colocated.contains(&key) => format!(r#"{}="{}{}{}""#, &caps[1], asset_prefix, key, &caps[3]),
+            _ => caps[0].to_string(),
+        }
+    }).into_owned()
+}
+
+fn collect_external_links(html: &str, page_path: &str, links: &mut std::collections::BTreeMap<String, Vec<String>>) {
+    // http(s) link targets of <a> tags; the fragment never reaches the server, so it's dropped
+    let href_pattern = Regex::new(r##"<a\b[^>]*?\shref="(https?://[^"#]+)"##).unwrap();
+    for caps in href_pattern.captures_iter(html) {
+        let pages = links.entry(unescape_html(&caps[1])).or_default();
+        if !pages.iter().any(|page| page == page_path) {
+            pages.push(page_path.to_string());
+        }
+    }
+}
+
+// Requests in flight at once while checking external links
+const EXTERNAL_LINK_WORKERS: usize = 8;
+
+fn probe_url(agent: &ureq::Agent, url: &str) -> Result<u16, String> {
+    // HEAD, following redirects; servers that refuse HEAD get a one-byte GET instead
+    let status = |response: Result<ureq::Response, ureq::Error>| match response {
+        Ok(response) => Ok(response.status()),
+        Err(ureq::Error::Status(status, _)) => Ok(status),
+        Err(ureq::Error::Transport(transport)) => {
+            // "Connect error: Connection refused (os error 111)", without the URL repeated
+            let message = transport.message().map_or_else(|| transport.kind().to_string(), str::to_string);
+            match std::error::Error::source(&transport) {
+                Some(source) => Err(format!("{}: {}", message, source)),
+                None => Err(message),
+            }
+        }
+    };
+    match status(agent.head(url).call())? {
+        405 | 501 => status(agent.get(url).set("Range", "bytes=0-0").call()),
+        code => Ok(code),
+    }
+}
+
+fn check_external_links(links: &std::collections::BTreeMap<String, Vec<String>>) {
+    // Each distinct URL is requested once; anything but a 2xx in the end is a warning
+    if links.is_empty() {
+        return;
+    }
+    let agent = ureq::AgentBuilder::new().timeout(std::time::Duration::from_secs(15)).build();
+    let urls: Vec<&String> = links.keys().collect();
+    let next = AtomicUsize::new(0);
+    let results = std::sync::Mutex::new(Vec::with_capacity(urls.len()));
+    std::thread::scope(|scope| {
+        for _ in 0..EXTERNAL_LINK_WORKERS.min(urls.len()) {
+            scope.spawn(|| {
+                while let Some(url) = urls.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let result = probe_url(&agent, url);
+                    if let Ok(mut results) = results.lock() {
+                        results.push((*url, result));
+                    }
+                }
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap_or_default();
+    results.sort();
+    let mut dead = 0;
+    for (url, result) in &results {
+        let problem = match result {
+            Ok(status) if (200..300).contains(status) => continue,
+            Ok(status) => format!("HTTP {}", status),
+            Err(message) => message.clone(),
+        };
+        dead += 1;
+        log_warn!("External link {} ({}) in {}", url, problem, links[*url].join(", "));
+    }
+    log_info!("Checked {} external link(s), {} broken", results.len(), dead);
+}
+
+fn write_output(path: &Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    // All files in dist/ are written through here so --dry-run can intercept them
+    record(&OUTPUT_FILES, path.display().to_string());
+    if is_dry_run() {
+        log_info!("[dry run] Would write: {}", path.display());
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)?;
+    log_verbose!("Generated: {}", path.display());
+    Ok(())
+}
+
+fn copy_output(source: &Path, dest: &Path) -> std::io::Result<()> {
+    record(&OUTPUT_FILES, dest.display().to_string());
+    if is_dry_run() {
+        log_info!("[dry run] Would copy: {} -> {}", source.display(), dest.display());
+        return Ok(());
+    }
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(source, dest)?;
+    log_verbose!("Copied: {} -> {}", source.display(), dest.display());
+    Ok(())
+}
+
+fn write_sitemap(dist_dir: &Path, site_url: &str, page_paths: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut sitemap = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for page_path in page_paths {
+        sitemap.push_str(&format!(
+            "  <url><loc>{}/{}</loc></url>\n",
+            escape_html(site_url.trim_end_matches('/')),
+            escape_html(page_path)
+        ));
+    }
+    sitemap.push_str("</urlset>\n");
+    write_output(&dist_dir.join("sitemap.xml"), sitemap)?;
+    Ok(())
+}
+
+fn write_robots_txt(dist_dir: &Path, site_url: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    // assets/robots.txt replaces the generated file entirely
+    let robots_path = dist_dir.join("robots.txt");
+    let custom_robots = Path::new("assets/robots.txt");
+    if custom_robots.exists() {
+        copy_output(custom_robots, &robots_path)?;
+        return Ok(());
+    }
+    let mut robots = String::from("User-agent: *\nAllow: /\n");
+    if let Some(site_url) = site_url {
+        robots.push_str(&format!("\nSitemap: {}/sitemap.xml\n", site_url.trim_end_matches('/')));
+    }
+    write_output(&robots_path, robots)?;
+    Ok(())
+}
+
+fn copy_assets_to_dist() -> Result<usize, Box<dyn std::error::Error>> {
+    let assets_dir = Path::new("assets");
+    let dist_assets_dir = Path::new("dist/assets");
+    
+    // Recursively copy all files and directories from assets to dist/assets
+    if assets_dir.exists() {
+        return copy_directory_recursive(assets_dir, dist_assets_dir);
+    }
     
-    for entry in fs::read_dir(dir)? {
+    Ok(0)
+}
+
+fn copy_directory_recursive(src: &Path, dst: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut copied = 0;
+    for entry in fs::read_dir(src)? {
         let entry = entry?;
         let path = entry.path();
+        let file_name = path.file_name().unwrap();
+        let dest_path = dst.join(file_name);
         
+        if path.is_dir() {
+            // Destination directories are created as files are copied into them
+            copied += copy_directory_recursive(&path, &dest_path)?;
+        } else {
+            copy_output(&path, &dest_path)?;
+            copied += 1;
+        }
+    }
+    
+    Ok(copied)
+}
+
+fn find_markdown_files(dir: &Path, base_dir: &Path, files: &mut Vec<(PathBuf, PathBuf, String)>, assets: &mut AssetList, skipped: &mut usize, ignore: &IgnoreRules, weights: &mut std::collections::HashMap<String, i32>) -> Result<(), Box<dyn std::error::Error>> {
+    if !dir.exists() {
+        return Ok(());
+    }
+    
+    // Sort entries so discovery order doesn't depend on the filesystem
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<PathBuf>, _>>()?;
+    entries.sort();
+    
+    for path in entries {
+        if path == base_dir.join(INCLUDES_DIR) {
+            continue;
+        }
+        let relative_key = path.strip_prefix(base_dir).unwrap_or(&path).to_string_lossy().replace('\\', "/");
+        if ignore.is_ignored(&relative_key, path.is_dir()) {
+            if is_markdown_file(&path) {
+                *skipped += 1;
+            }
+            continue;
+        }
         if path.is_dir() {
             // Recursively search subdirectories
-            find_markdown_files(&path, base_dir, files)?;
-        } else if path.extension().and_then(|s| s.to_str()) == Some("md") {
+            find_markdown_files(&path, base_dir, files, assets, skipped, ignore, weights)?;
+        } else if !is_markdown_file(&path) {
+            // Anything else next to the pages (images, PDFs, ...) is published alongside them,
+            // except hidden files and anything in a hidden directory (.git/, .obsidian/, ...)
+            let hidden = relative_key.split('/').any(|part| part.starts_with('.'));
+            if !hidden {
+                let relative_path = path.strip_prefix(base_dir).unwrap_or(&path).to_path_buf();
+                assets.push((path.clone(), relative_path));
+            }
+        } else {
             // Skip README.md files (case-insensitive)
             if let Some(filename) = path.file_stem().and_then(|s| s.to_str()) {
                 if filename.eq_ignore_ascii_case("README") {
+                    *skipped += 1;
                     continue;
                 }
             }
@@ -797,24 +3123,54 @@ fn find_markdown_files(dir: &Path, base_dir: &Path, files: &mut Vec<(PathBuf, Pa
             
             // Skip files that are already HTML (not markdown)
             if content.trim_start().starts_with("<!DOCTYPE") || content.trim_start().starts_with("<html") {
+                *skipped += 1;
                 continue;
             }
             
             let (frontmatter, _) = extract_frontmatter(&content);
+            if frontmatter.as_ref().and_then(|fm| fm.draft) == Some(true) {
+                log_verbose!("Skipping draft: {}", path.display());
+                *skipped += 1;
+                continue;
+            }
+            let slug = frontmatter.as_ref().and_then(|fm| fm.slug.clone());
+            let weight = frontmatter.as_ref().and_then(|fm| fm.weight);
             let title = frontmatter
                 .and_then(|fm| fm.title)
                 .unwrap_or_else(|| {
                     path.file_stem()
                         .and_then(|s| s.to_str())
+                        .map(|stem| stem.trim_end_matches(".html"))
                         .unwrap_or("Untitled")
                         .to_string()
                 });
             
             // Calculate relative path from base_dir
-            let relative_path = path.strip_prefix(base_dir)
+            let mut relative_path = path.strip_prefix(base_dir)
                 .unwrap_or(&path)
                 .to_path_buf();
+            // landing.html.md publishes to landing.html like any other page
+            if is_raw_html_source(&path) {
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("md");
+                    relative_path.set_file_name(format!("{}.{}", stem.trim_end_matches(".html"), ext));
+                }
+            }
+            
+            // A slug renames the output file but keeps it in the same directory
+            if let Some(slug) = slug {
+                let slug = slug.trim().trim_end_matches(".html");
+                if slug.is_empty() || slug.contains('/') || slug.contains('\\') {
+                    log_warn!("Ignoring invalid slug '{}' in {}", slug, path.display());
+                } else {
+                    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("md");
+                    relative_path.set_file_name(format!("{}.{}", slug, ext));
+                }
+            }
             
+            if let Some(weight) = weight {
+                weights.insert(page_key(&relative_path), weight);
+            }
             files.push((path.clone(), relative_path, title));
         }
     }
@@ -822,239 +3178,798 @@ fn find_markdown_files(dir: &Path, base_dir: &Path, files: &mut Vec<(PathBuf, Pa
     Ok(())
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let content_dir = Path::new("content");
-    let dist_dir = Path::new("dist");
-    
-    // Create content directory if it doesn't exist
-    if !content_dir.exists() {
-        fs::create_dir_all(content_dir)?;
+fn warn_unknown_config_keys(config: &Config, config_path: &Path) {
+    for key in config.unknown.keys() {
+        log_warn!("Unknown key '{}' in {}", key, config_path.display());
     }
-    
-    // Create dist directory if it doesn't exist
-    if !dist_dir.exists() {
-        fs::create_dir_all(dist_dir)?;
+    for key in config.markdown.unknown.keys() {
+        log_warn!("Unknown key 'markdown.{}' in {}", key, config_path.display());
     }
-    
-    // Find all markdown files recursively (full_path, relative_path, title)
-    let mut markdown_files: Vec<(PathBuf, PathBuf, String)> = Vec::new();
-    find_markdown_files(content_dir, content_dir, &mut markdown_files)?;
+}
 
-    // Build a map of markdown file paths (without extension) to titles
-    // Use relative path as key (e.g., "math/sir" for "content/math/sir.md")
-    let mut markdown_titles: std::collections::HashMap<String, String> = std::collections::HashMap::new();
-    for (_, relative_path, title) in &markdown_files {
-        // Convert relative path to string key (without .md extension)
-        let key = relative_path.with_extension("")
-            .to_string_lossy()
-            .replace('\\', "/"); // Normalize path separators
-        markdown_titles.insert(key, title.clone());
+fn warn_missing_dropdown_pages(
+    dropdowns: &std::collections::HashMap<String, serde_yaml::Value>,
+    markdown_titles: &std::collections::HashMap<String, String>,
+) {
+    // Same lookup generate_navbar uses: an exact key, or a file name anywhere in the tree
+    let page_exists = |name: &str| {
+        markdown_titles.contains_key(name)
+            || markdown_titles.keys().any(|k| k.ends_with(&format!("/{}", name)))
+    };
+
+    let mut names: Vec<&String> = dropdowns.keys().collect();
+    names.sort();
+    for name in names {
+        match &dropdowns[name] {
+            serde_yaml::Value::Sequence(seq) => {
+                for page_name in seq.iter().filter_map(|item| item.as_str()) {
+                    if !page_exists(page_name) {
+                        log_warn!("Dropdown '{}' lists '{}', but no such page exists", name, page_name);
+                    }
+                }
+            }
+            serde_yaml::Value::Mapping(map) => {
+                // Only relative links to generated pages can be checked
+                for (key, value) in map {
+                    let url = value.as_str().unwrap_or("");
+                    let target = url.split(['#', '?']).next().unwrap_or("");
+                    let Some(page_name) = target.strip_suffix(".html") else { continue };
+                    if is_external_url(url) {
+                        continue;
+                    }
+                    let page_name = page_name.trim_start_matches("./");
+                    if !page_exists(page_name) {
+                        log_warn!(
+                            "Dropdown '{}' entry '{}' links to '{}', but no such page exists",
+                            name,
+                            key.as_str().unwrap_or(""),
+                            url
+                        );
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Implicit config files in order of preference
+const DEFAULT_CONFIG_FILES: &[&str] = &["config.yaml", "config.toml", "config.json"];
+
+fn default_config_path() -> PathBuf {
+    DEFAULT_CONFIG_FILES.iter()
+        .map(PathBuf::from)
+        .find(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_FILES[0]))
+}
+
+fn parse_config(content: &str, config_path: &Path) -> Result<Config, String> {
+    // The format follows the extension; anything other than .toml or .json is YAML
+    match config_path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str::<Config>(content).map_err(|e| e.to_string()),
+        Some("json") => serde_json::from_str::<Config>(content).map_err(|e| e.to_string()),
+        _ => serde_yaml::from_str::<Config>(content).map_err(|e| e.to_string()),
     }
+}
 
-    // Load config file if it exists
-    let config_path = Path::new("config.yaml");
-    let (page_order, navbar_order, dropdowns) = if config_path.exists() {
+fn load_config(config_path: &Path) -> Result<Config, Box<dyn std::error::Error>> {
+    // An explicitly requested config must exist and parse
+    let content = fs::read_to_string(config_path)
+        .map_err(|e| format!("Failed to read {}: {}", config_path.display(), e))?;
+    let config = parse_config(&content, config_path)
+        .map_err(|e| format!("Failed to parse {}: {}", config_path.display(), e))?;
+    warn_unknown_config_keys(&config, config_path);
+    Ok(config)
+}
+
+fn load_default_config(config_path: &Path) -> Config {
+    // The implicit config file is optional, so problems only warn
+    let ignored: Vec<&str> = DEFAULT_CONFIG_FILES.iter().copied()
+        .filter(|name| Path::new(name) != config_path && Path::new(name).exists())
+        .collect();
+    if !ignored.is_empty() {
+        log_warn!("Using {} and ignoring {}", config_path.display(), ignored.join(", "));
+    }
+    if config_path.exists() {
         match fs::read_to_string(config_path) {
             Ok(content) => {
-                match serde_yaml::from_str::<Config>(&content) {
-                    Ok(config) => (config.page_order, config.navbar_order, config.dropdowns),
+                match parse_config(&content, config_path) {
+                    Ok(config) => {
+                        warn_unknown_config_keys(&config, config_path);
+                        config
+                    }
                     Err(e) => {
-                        eprintln!("Warning: Failed to parse config.yaml: {}", e);
-                        (None, None, None)
+                        log_warn!("Failed to parse {}: {}", config_path.display(), e);
+                        Config::default()
                     }
                 }
             }
             Err(e) => {
-                eprintln!("Warning: Failed to read config.yaml: {}", e);
-                (None, None, None)
+                log_warn!("Failed to read {}: {}", config_path.display(), e);
+                Config::default()
             }
         }
     } else {
-        (None, None, None)
-    };
+        Config::default()
+    }
+}
 
-    // Sort markdown files according to config or alphabetically
-    if let Some(ref order) = page_order {
-        // Separate index from other pages
-        let mut index_file: Option<(PathBuf, PathBuf, String)> = None;
-        let mut other_files: Vec<(PathBuf, PathBuf, String)> = Vec::new();
-        
-        for file in markdown_files {
-            let relative_key = file.1.with_extension("")
-                .to_string_lossy()
-                .replace('\\', "/");
-            if relative_key == "index" {
-                index_file = Some(file);
-            } else {
-                other_files.push(file);
-            }
-        }
-        
-        // Sort other files according to config order
-        other_files.sort_by(|a, b| {
-            let a_key = a.1.with_extension("")
-                .to_string_lossy()
-                .replace('\\', "/");
-            let b_key = b.1.with_extension("")
-                .to_string_lossy()
-                .replace('\\', "/");
-            
-            // Check if config matches just the filename or the full path
-            let a_pos = order.iter().position(|x| {
-                if let Some(page_name) = x.as_str() {
-                    page_name == &a_key || a_key.ends_with(&format!("/{}", page_name))
-                } else {
-                    false
+#[derive(Default)]
+struct BuildOptions {
+    log_level: LogLevel,  // --quiet or --verbose
+    content_dirs: Vec<PathBuf>,  // Repeated --content-dir flags; overrides content_dirs in config
+    dry_run: bool,        // Report what would be written without touching dist/
+    config_path: Option<PathBuf>,  // Explicit --config file; must exist when given
+    init: bool,           // --init: write a starter site instead of building
+    new_page: Option<String>,  // --new: scaffold this page (e.g. "math/new-topic") instead of building
+    lint: bool,           // --lint: check content for problems without building
+    only: Option<PathBuf>,  // --only: render just this source file (the site is still scanned)
+    profile: bool,        // --profile: print time spent per build phase
+    json_report: bool,    // --report-format json: print the build report as JSON on stdout
+    strict: bool,         // --strict: fail the build if anything warned
+    check_external_links: bool,  // --check-external-links: request every external link (needs network)
+}
+
+impl BuildOptions {
+    fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut options = BuildOptions::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--verbose" | "-v" => options.log_level = LogLevel::Verbose,
+                "--quiet" | "-q" => options.log_level = LogLevel::Quiet,
+                "--dry-run" | "-n" => options.dry_run = true,
+                "--init" => options.init = true,
+                "--new" => {
+                    let name = args.next().ok_or("--new requires a page name, e.g. math/new-topic")?;
+                    options.new_page = Some(name.clone());
                 }
-            });
-            let b_pos = order.iter().position(|x| {
-                if let Some(page_name) = x.as_str() {
-                    page_name == &b_key || b_key.ends_with(&format!("/{}", page_name))
-                } else {
-                    false
+                flag if flag.starts_with("--new=") => {
+                    options.new_page = Some(flag["--new=".len()..].to_string());
                 }
-            });
-            
-            match (a_pos, b_pos) {
-                (Some(a_idx), Some(b_idx)) => a_idx.cmp(&b_idx),
-                (Some(_), None) => std::cmp::Ordering::Less,
-                (None, Some(_)) => std::cmp::Ordering::Greater,
-                (None, None) => a_key.cmp(&b_key), // Alphabetical fallback for unlisted files
+                "--profile" => options.profile = true,
+                "--strict" => options.strict = true,
+                "--check-external-links" => options.check_external_links = true,
+                "--report-format" => {
+                    let format = args.next().ok_or("--report-format requires text or json")?;
+                    options.json_report = parse_report_format(format)?;
+                }
+                flag if flag.starts_with("--report-format=") => {
+                    options.json_report = parse_report_format(&flag["--report-format=".len()..])?;
+                }
+                "--lint" | "check" => options.lint = true,
+                "--config" => {
+                    let path = args.next().ok_or("--config requires a file path")?;
+                    options.config_path = Some(PathBuf::from(path));
+                }
+                flag if flag.starts_with("--config=") => {
+                    options.config_path = Some(PathBuf::from(&flag["--config=".len()..]));
+                }
+                "--only" => {
+                    let path = args.next().ok_or("--only requires a markdown file")?;
+                    options.only = Some(PathBuf::from(path));
+                }
+                flag if flag.starts_with("--only=") => {
+                    options.only = Some(PathBuf::from(&flag["--only=".len()..]));
+                }
+                "--content-dir" => {
+                    let path = args.next().ok_or("--content-dir requires a directory")?;
+                    options.content_dirs.push(PathBuf::from(path));
+                }
+                flag if flag.starts_with("--content-dir=") => {
+                    options.content_dirs.push(PathBuf::from(&flag["--content-dir=".len()..]));
+                }
+                // Positional arguments (e.g. `build`) are accepted for compatibility
+                flag if flag.starts_with('-') => return Err(format!("Unknown option: {}", flag)),
+                _ => {}
             }
-        });
-        
-        // Reconstruct with index first
-        let mut sorted_files = Vec::new();
-        if let Some(index) = index_file {
-            sorted_files.push(index);
         }
-        sorted_files.extend(other_files);
-        markdown_files = sorted_files;
-    } else {
-        // Default: sort alphabetically, but keep index first
-        markdown_files.sort_by(|a, b| {
-            let a_key = a.1.with_extension("")
-                .to_string_lossy()
-                .replace('\\', "/");
-            let b_key = b.1.with_extension("")
-                .to_string_lossy()
-                .replace('\\', "/");
-            
-            match (a_key == "index", b_key == "index") {
-                (true, false) => std::cmp::Ordering::Less,
-                (false, true) => std::cmp::Ordering::Greater,
-                _ => a_key.cmp(&b_key),
-            }
-        });
+        Ok(options)
     }
+}
 
-    // Filter out pages that are in sequence dropdowns (like Resources) from the regular navbar
-    // Pages in mapping dropdowns (like Syllabi) should still appear in navbar
-    let mut pages_in_dropdowns: std::collections::HashSet<String> = std::collections::HashSet::new();
-    if let Some(ref dropdowns_map) = dropdowns {
-        for dropdown_value in dropdowns_map.values() {
-            match dropdown_value {
-                serde_yaml::Value::Sequence(seq) => {
-                    // For sequences (like Resources), the items are page names that should be hidden
-                    for item in seq {
-                        if let Some(page_name) = item.as_str() {
-                            pages_in_dropdowns.insert(page_name.to_string());
-                        }
-                    }
-                }
-                // For mappings (like Syllabi), we don't hide the pages - they're just for linking to syllabi
-                _ => {}
-            }
+fn parse_report_format(format: &str) -> Result<bool, String> {
+    match format {
+        "text" => Ok(false),
+        "json" => Ok(true),
+        other => Err(format!("Unknown report format '{}', expected text or json", other)),
+    }
+}
+
+#[derive(Debug, Default)]
+struct BuildReport {
+    pages_rendered: usize,
+    pages_cached: usize,   // Unchanged pages kept from the previous build (build_cache)
+    pages_skipped: usize,  // README, standalone HTML and draft files found under content/
+    assets_copied: usize,
+    bytes_saved: usize,    // Reduction from minifying pages, when enabled
+    files_compressed: usize,  // .gz/.br files written by precompress
+    bytes_compressed: usize,  // Their total size
+    elapsed: std::time::Duration,
+}
+
+impl BuildReport {
+    fn print_summary(&self) {
+        // Dry runs are labelled so nobody mistakes them for a real build
+        if is_dry_run() {
+            log_info!(
+                "Dry run, nothing was written: would build {} page(s), copy {} asset(s), skip {} file(s)",
+                self.pages_rendered,
+                self.assets_copied,
+                self.pages_skipped
+            );
+            return;
+        }
+        log_info!(
+            "Built {} page(s), copied {} asset(s), skipped {} file(s) in {:.2}s",
+            self.pages_rendered,
+            self.assets_copied,
+            self.pages_skipped,
+            self.elapsed.as_secs_f64()
+        );
+        if self.pages_cached > 0 {
+            log_info!("Reused {} unchanged page(s) from the build cache", self.pages_cached);
+        }
+        if self.bytes_saved > 0 {
+            log_info!("Minified HTML, saving {:.1} KB", self.bytes_saved as f64 / 1024.0);
+        }
+        if self.files_compressed > 0 {
+            log_info!(
+                "Precompressed {} file(s), {:.1} KB in total",
+                self.files_compressed,
+                self.bytes_compressed as f64 / 1024.0
+            );
         }
     }
-    
-    // Build navbar items from navbar_order, page_order, or markdown files
-    let mut navbar_items: Vec<NavbarItem> = Vec::new();
-    
-    if let Some(ref order) = navbar_order {
-        // Use navbar_order if specified - allows full control including dropdowns
-        for item in order {
-            match item {
+
+    fn to_json(&self, error: Option<String>) -> serde_json::Value {
+        let profile: serde_json::Map<String, serde_json::Value> = PHASE_NAMES.iter().zip(&PHASE_NANOS)
+            .map(|(name, nanos)| (name.to_lowercase().replace(' ', "_"), (nanos.load(Ordering::Relaxed) as f64 / 1e9).into()))
+            .collect();
+        let collected = |list: &std::sync::Mutex<Vec<String>>| list.lock().map(|list| list.clone()).unwrap_or_default();
+        serde_json::json!({
+            "success": error.is_none(),
+            "error": error,
+            "dry_run": is_dry_run(),
+            "pages_rendered": self.pages_rendered,
+            "pages_cached": self.pages_cached,
+            "pages_skipped": self.pages_skipped,
+            "assets_copied": self.assets_copied,
+            "bytes_saved": self.bytes_saved,
+            "files_compressed": self.files_compressed,
+            "bytes_compressed": self.bytes_compressed,
+            "elapsed_seconds": self.elapsed.as_secs_f64(),
+            "profile_seconds": PROFILE.load(Ordering::Relaxed).then_some(profile),
+            "files": collected(&OUTPUT_FILES),
+            "warnings": collected(&WARNINGS),
+        })
+    }
+
+    fn print_profile(&self) {
+        // Phases don't overlap; the remainder of the build (I/O, navbar, templates) isn't broken out
+        log_info!("Profile (cumulative):");
+        for (name, nanos) in PHASE_NAMES.iter().zip(&PHASE_NANOS) {
+            let seconds = nanos.load(Ordering::Relaxed) as f64 / 1e9;
+            let share = if self.elapsed.is_zero() { 0.0 } else { 100.0 * seconds / self.elapsed.as_secs_f64() };
+            log_info!("  {:<18} {:>8.3}s  {:>5.1}%", name, seconds, share);
+        }
+    }
+}
+
+const STARTER_INDEX: &str = r#"---
+title: Home
+---
+
+# Welcome
+
+This site was generated by `ideeep --init`. Edit `content/index.md` to get started.
+
+Every `.md` file under `content/` becomes a page, e.g. `content/about.md` is built to `dist/about.html`.
+Inline math like $e^{i\pi} + 1 = 0$ is rendered with KaTeX.
+"#;
+
+const STARTER_CONFIG: &str = r#"# Site configuration. Every key is optional.
+
+# Pages listed here come first in the navbar, in this order
+# page_order:
+#   - about
+
+# Full control over the navbar, including dropdown menus and external links
+# navbar_order:
+#   - about
+#   - Resources
+#   - url: https://example.com
+#     text: Home
+
+# Dropdown menus referenced by name from navbar_order
+# dropdowns:
+#   Resources:
+#     - notes
+#     - links
+
+logo: assets/logo.svg
+favicon: assets/logo.svg
+"#;
+
+const STARTER_LOGO: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" width="120" height="40" viewBox="0 0 120 40">
+  <rect width="120" height="40" rx="6" fill="#2c3e50"/>
+  <text x="60" y="26" font-family="sans-serif" font-size="16" fill="#ffffff" text-anchor="middle">My Site</text>
+</svg>
+"##;
+
+const STARTER_FOOTER: &str = r#"<footer>
+  <p>&copy; {{year}} My Site. Built with ideeep.</p>
+</footer>
+"#;
+
+fn init_site(root: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let files: [(&str, &str); 5] = [
+        ("content/index.md", STARTER_INDEX),
+        ("config.yaml", STARTER_CONFIG),
+        ("assets/logo.svg", STARTER_LOGO),
+        ("assets/footer.html", STARTER_FOOTER),
+        ("assets/styles.css", include_str!("../assets/styles.css")),
+    ];
+
+    // Check everything up front so a partially existing site is left untouched
+    let existing: Vec<&str> = files
+        .iter()
+        .map(|(path, _)| *path)
+        .filter(|path| root.join(path).exists())
+        .collect();
+    if !existing.is_empty() {
+        return Err(format!("--init will not overwrite existing files: {}", existing.join(", ")).into());
+    }
+
+    for (path, contents) in files {
+        let target = root.join(path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&target, contents)?;
+        log_info!("Created: {}", path);
+    }
+
+    log_info!("\nNext steps:");
+    log_info!("  1. Edit content/index.md and add more .md files under content/");
+    log_info!("  2. Uncomment the examples in config.yaml to arrange the navbar");
+    log_info!("  3. Run ideeep again (without --init) to build the site into dist/");
+    Ok(())
+}
+
+fn new_page(options: &BuildOptions, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    // "math/new-topic" becomes <first content dir>/math/new-topic.md with a draft front matter stub
+    let config = match options.config_path {
+        Some(ref path) => load_config(path)?,
+        None => load_default_config(&default_config_path()),
+    };
+    let name = name.trim_matches('/');
+    let name = name.strip_suffix(".md").unwrap_or(name);
+    let relative = Path::new(name);
+    if name.is_empty() || !relative.components().all(|c| matches!(c, std::path::Component::Normal(_))) {
+        return Err(format!("--new expects a page path inside the content directory, got '{}'", name).into());
+    }
+    let content_dir = resolve_content_dirs(options, &config).into_iter().next()
+        .unwrap_or_else(|| PathBuf::from("content"));
+    let target = content_dir.join(format!("{}.md", name));
+    if target.exists() {
+        return Err(format!("--new will not overwrite existing file: {}", target.display()).into());
+    }
+
+    let (year, month, day) = today();
+    let stub = format!(
+        "---\ntitle: {}\ndate: {:04}-{:02}-{:02}\ndraft: true\n---\n\n",
+        serde_json::to_string(&section_title(name))?, year, month, day
+    );
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&target, stub)?;
+    log_info!("Created: {}", target.display());
+    log_info!("It stays out of the build until you remove `draft: true`");
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = BuildOptions::from_args(&args)?;
+    set_log_level(options.log_level);
+    if options.init {
+        return init_site(Path::new("."));
+    }
+    if let Some(ref name) = options.new_page {
+        return new_page(&options, name);
+    }
+    if options.lint {
+        let issues = lint_site(&options)?;
+        if issues > 0 {
+            return Err(format!("{} problem(s) found", issues).into());
+        }
+        log_info!("No problems found");
+        return Ok(());
+    }
+    DRY_RUN.store(options.dry_run, Ordering::Relaxed);
+    PROFILE.store(options.profile, Ordering::Relaxed);
+    if options.json_report {
+        // Keep stdout to the JSON document; warnings are collected into it instead
+        set_log_level(LogLevel::Quiet);
+        let result = build_site(&options);
+        let (report, error) = match result {
+            Ok(report) => (report, strict_failure(&options)),
+            Err(e) => (BuildReport::default(), Some(e.to_string())),
+        };
+        println!("{}", serde_json::to_string_pretty(&report.to_json(error.clone()))?);
+        return match error {
+            Some(e) => Err(e.into()),
+            None => Ok(()),
+        };
+    }
+    let report = build_site(&options)?;
+    report.print_summary();
+    if options.profile {
+        report.print_profile();
+    }
+    if let Some(failure) = strict_failure(&options) {
+        // --quiet hid the warnings as they happened, so list them once here
+        if !log_enabled(LogLevel::Normal) {
+            for warning in WARNINGS.lock().map(|list| list.clone()).unwrap_or_default() {
+                eprintln!("Warning: {}", warning);
+            }
+        }
+        return Err(failure.into());
+    }
+    Ok(())
+}
+
+fn strict_failure(options: &BuildOptions) -> Option<String> {
+    // With --strict the build runs to the end, then fails if anything warned along the way
+    let count = WARNINGS.lock().map(|list| list.len()).unwrap_or(0);
+    (options.strict && count > 0).then(|| format!("{} warning(s) with --strict", count))
+}
+
+const BUILD_CACHE_FILE: &str = ".ideeep-cache.json";
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct BuildCache {
+    fingerprint: String,  // Hash of everything that affects every page
+    pages: std::collections::BTreeMap<String, String>,  // Output page path -> page content hash
+}
+
+impl BuildCache {
+    fn load(dist_dir: &Path, fingerprint: &str) -> Self {
+        // A missing, unreadable or outdated cache just means every page is rendered
+        let cache = fs::read_to_string(dist_dir.join(BUILD_CACHE_FILE))
+            .ok()
+            .and_then(|text| serde_json::from_str::<BuildCache>(&text).ok())
+            .filter(|cache| cache.fingerprint == fingerprint);
+        cache.unwrap_or_else(|| BuildCache { fingerprint: fingerprint.to_string(), ..Default::default() })
+    }
+
+    fn is_fresh(&self, page_path: &str, hash: &str, html_path: &Path) -> bool {
+        self.pages.get(page_path).is_some_and(|cached| cached == hash) && html_path.exists()
+    }
+
+    fn save(&self, dist_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        write_output(&dist_dir.join(BUILD_CACHE_FILE), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+fn hash_hex(parts: &[&[u8]]) -> String {
+    // Only compared against hashes from earlier builds, so std's hasher is good enough
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    for part in parts {
+        part.hash(&mut hasher);
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+fn generator_fingerprint() -> String {
+    // Size and modification time of the running binary, so pages rendered by an older build of
+    // the generator are never reused, version bump or not. Cheaper than hashing the binary, and a
+    // false change (e.g. a reinstall) only costs one full render.
+    let metadata = std::env::current_exe().and_then(fs::metadata);
+    let modified = metadata.as_ref().ok()
+        .and_then(|m| m.modified().ok())
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok());
+    match (metadata, modified) {
+        (Ok(metadata), Some(modified)) => format!("{}-{}-{}", env!("CARGO_PKG_VERSION"), metadata.len(), modified.as_nanos()),
+        _ => env!("CARGO_PKG_VERSION").to_string(),
+    }
+}
+
+fn site_fingerprint(
+    generator: &str,
+    config_file: &Path,
+    assets_dir: &Path,
+    markdown_files: &[(PathBuf, PathBuf, String)],
+    includes_dirs: &[PathBuf],
+) -> String {
+    // Generator, config, templates, footer, includes and the page list (titles, paths and order
+    // feed the navbar, links and prev/next on every page); any change invalidates the whole cache
+    let mut files: Vec<PathBuf> = vec![
+        config_file.to_path_buf(),
+        assets_dir.join("template.html"),
+        assets_dir.join("footer.html"),
+    ];
+    let template_dirs = [assets_dir.join("templates"), assets_dir.join("footers")];
+    for dir in includes_dirs.iter().chain(&template_dirs) {
+        if let Ok(entries) = fs::read_dir(dir) {
+            let mut paths: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+            paths.sort();
+            files.extend(paths);
+        }
+    }
+    let mut parts: Vec<Vec<u8>> = vec![generator.as_bytes().to_vec()];
+    for file in &files {
+        parts.push(file.to_string_lossy().as_bytes().to_vec());
+        parts.push(fs::read(file).unwrap_or_default());
+    }
+    for (full_path, relative_path, title) in markdown_files {
+        parts.push(format!("{}\0{}\0{}", full_path.display(), relative_path.display(), title).into_bytes());
+    }
+    hash_hex(&parts.iter().map(Vec::as_slice).collect::<Vec<_>>())
+}
+
+fn resolve_content_dirs(options: &BuildOptions, config: &Config) -> Vec<PathBuf> {
+    // Content roots: --content-dir flags, then content_dirs from config, then content/
+    if !options.content_dirs.is_empty() {
+        options.content_dirs.clone()
+    } else if let Some(ref dirs) = config.content_dirs {
+        dirs.iter().map(PathBuf::from).collect()
+    } else {
+        vec![PathBuf::from("content")]
+    }
+}
+
+fn content_root(content_dirs: &[PathBuf], full_path: &Path) -> PathBuf {
+    content_dirs.iter().rev()
+        .find(|root| full_path.starts_with(root))
+        .cloned()
+        .unwrap_or_default()
+}
+
+type PageList = Vec<(PathBuf, PathBuf, String)>;  // (full_path, relative_path, title)
+type AssetList = Vec<(PathBuf, PathBuf)>;  // (full_path, relative_path) of non-markdown content files
+type PageWeights = std::collections::HashMap<String, i32>;  // page key -> front matter weight
+
+fn discover_pages(content_dirs: &[PathBuf], skipped: &mut usize) -> Result<(PageList, PageWeights, AssetList), Box<dyn std::error::Error>> {
+    // Roots are merged into one namespace; a page in a later root replaces the same path in an earlier one.
+    let mut markdown_files: PageList = Vec::new();
+    let mut assets: AssetList = Vec::new();
+    let ignore = IgnoreRules::load(Path::new(".ideepignore"))?;
+    let mut page_weights: std::collections::HashMap<String, i32> = std::collections::HashMap::new();
+    for root in content_dirs {
+        let mut root_files: PageList = Vec::new();
+        let mut root_weights: std::collections::HashMap<String, i32> = std::collections::HashMap::new();
+        let mut root_assets: AssetList = Vec::new();
+        find_markdown_files(root, root, &mut root_files, &mut root_assets, skipped, &ignore, &mut root_weights)?;
+        for asset in root_assets {
+            assets.retain(|(_, relative_path)| *relative_path != asset.1);
+            assets.push(asset);
+        }
+        for file in root_files {
+            let source = file.0.strip_prefix(root).unwrap_or(&file.0).to_path_buf();
+            let existing = markdown_files.iter().position(|(full_path, _, _)| {
+                full_path.strip_prefix(content_root(content_dirs, full_path)).map(|p| p == source).unwrap_or(false)
+            });
+            if let Some(index) = existing {
+                let replaced = markdown_files.remove(index);
+                log_verbose!("Override: {} replaces {}", file.0.display(), replaced.0.display());
+                page_weights.remove(&page_key(&replaced.1));
+            }
+            markdown_files.push(file);
+        }
+        page_weights.extend(root_weights);
+    }
+    Ok((markdown_files, page_weights, assets))
+}
+
+fn lint_site(options: &BuildOptions) -> Result<usize, Box<dyn std::error::Error>> {
+    // Same discovery as a build, but nothing is rendered or written; each problem is
+    // printed as `path: message` and the count is returned
+    let config = match options.config_path {
+        Some(ref path) => load_config(path)?,
+        None => load_default_config(&default_config_path()),
+    };
+    let content_dirs = resolve_content_dirs(options, &config);
+    let includes_dirs: Vec<PathBuf> = content_dirs.iter().map(|root| root.join(INCLUDES_DIR)).collect();
+    let mut skipped = 0;
+    let (markdown_files, _, _) = discover_pages(&content_dirs, &mut skipped)?;
+
+    let mut issues: Vec<(PathBuf, String)> = Vec::new();
+
+    // Output keys and every name a link may use for them (source name and slug)
+    let mut page_keys: std::collections::HashMap<String, String> = std::collections::HashMap::new();
+    let mut outputs: std::collections::HashMap<String, &PathBuf> = std::collections::HashMap::new();
+    for (full_path, relative_path, _) in &markdown_files {
+        let output_key = page_key(relative_path);
+        let source_key = page_key(full_path.strip_prefix(content_root(&content_dirs, full_path)).unwrap_or(full_path));
+        page_keys.insert(source_key, output_key.clone());
+        page_keys.insert(output_key.clone(), output_key.clone());
+        if let Some(other) = outputs.insert(output_key.clone(), full_path) {
+            issues.push((full_path.clone(), format!("publishes to {}.html, same as {}", output_key, other.display())));
+        }
+    }
+    let page_exists = |name: &str| {
+        page_keys.keys().any(|key| key == name || key.ends_with(&format!("/{}", name)))
+    };
+
+    for (full_path, relative_path, _) in &markdown_files {
+        let content = fs::read_to_string(full_path)?;
+        let body = match parse_frontmatter(&content) {
+            Ok(Some((fm, body))) => {
+                if fm.title.as_deref().is_none_or(|t| t.trim().is_empty()) {
+                    issues.push((full_path.clone(), "missing title".to_string()));
+                }
+                body
+            }
+            Ok(None) => {
+                issues.push((full_path.clone(), "missing title".to_string()));
+                content.as_str()
+            }
+            Err(e) => {
+                issues.push((full_path.clone(), format!("front matter does not parse: {}", e)));
+                content.as_str()
+            }
+        };
+        if body.trim().is_empty() {
+            issues.push((full_path.clone(), "empty page".to_string()));
+            continue;
+        }
+        let markdown = match expand_includes(body, &includes_dirs, &mut vec![full_path.clone()]) {
+            Ok(markdown) => markdown,
+            Err(e) => {
+                issues.push((full_path.clone(), e.to_string()));
+                continue;
+            }
+        };
+
+        // Internal links are checked the way convert_internal_links resolves them
+        let page_dir = relative_path.parent().map(page_key).unwrap_or_default();
+        for event in Parser::new_ext(&markdown, Options::all()) {
+            let pulldown_cmark::Event::Start(pulldown_cmark::Tag::Link(_, dest, _)) = event else { continue };
+            if dest.starts_with('#') || dest.starts_with("mailto:") || is_external_url(&dest) {
+                continue;
+            }
+            let target = dest.split(['#', '?']).next().unwrap_or("");
+            let stem = match strip_markdown_extension(target) {
+                Some(stem) => stem,
+                None if !target.is_empty() && !target.contains('.') => target,
+                None => continue,
+            };
+            let relative = if page_dir.is_empty() { stem.to_string() } else { format!("{}/{}", page_dir, stem) };
+            let dir_index = format!("{}/index", stem.trim_end_matches('/'));
+            if !page_keys.contains_key(&normalize_page_key(&relative)) && !page_exists(stem) && !page_exists(&dir_index) {
+                issues.push((full_path.clone(), format!("broken link to '{}'", dest)));
+            }
+        }
+    }
+
+    for (path, message) in &issues {
+        println!("{}: {}", path.display(), message);
+    }
+    Ok(issues.len())
+}
+
+fn normalize_page_key(key: &str) -> String {
+    // "math/../programming/intro" -> "programming/intro"
+    let mut parts: Vec<&str> = Vec::new();
+    for part in key.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+fn build_navbar_items(
+    config: &Config,
+    markdown_files: &[(PathBuf, PathBuf, String)],
+    pages_in_dropdowns: &std::collections::HashSet<String>,
+) -> Vec<NavbarItem> {
+    // navbar_exclude hides pages (e.g. "blog/" or "drafts/*") from listings and from globs in
+    // navbar_order and page_order; index and pages named explicitly there are always kept
+    let navbar_excluded = |key: &str| {
+        key != "index" && config.navbar_exclude.iter().flatten().any(|entry| location_matches(entry, key))
+    };
+
+    // Build navbar items from navbar_order, page_order, or markdown files
+    let mut navbar_items: Vec<NavbarItem> = Vec::new();
+    
+    if let Some(ref order) = config.navbar_order {
+        // Use navbar_order if specified - allows full control including dropdowns
+        for item in order {
+            match item {
                 serde_yaml::Value::String(page_name) => {
                     // Check if it's a dropdown name
-                    if let Some(ref dropdowns_map) = dropdowns {
+                    if let Some(ref dropdowns_map) = config.dropdowns {
                         if dropdowns_map.contains_key(page_name) {
                             navbar_items.push(NavbarItem::Dropdown(page_name.clone()));
                             continue;
                         }
                     }
-                    // Otherwise treat as markdown file name (can be filename or path like "math/sir")
-                    if let Some((full_path, relative_path, title)) = markdown_files.iter()
-                        .find(|(_, rel_path, _)| {
-                            let rel_key = rel_path.with_extension("")
-                                .to_string_lossy()
-                                .replace('\\', "/");
-                            rel_key == *page_name || rel_key.ends_with(&format!("/{}", page_name))
-                        })
-                        .cloned()
-                    {
-                        let rel_key = relative_path.with_extension("")
-                            .to_string_lossy()
-                            .replace('\\', "/");
+                    // Otherwise treat as markdown file name (can be filename, path like "math/sir", or glob)
+                    for (_, relative_path, title) in find_order_matches(markdown_files, page_name) {
+                        let rel_key = page_key(relative_path);
+                        if page_name.contains('*') && navbar_excluded(&rel_key) {
+                            continue;
+                        }
                         // Skip index (already added with logo)
                         if rel_key != "index" {
-                            navbar_items.push(NavbarItem::MarkdownFile(relative_path, title));
+                            navbar_items.push(NavbarItem::MarkdownFile(relative_path.clone(), title.clone()));
                         }
                     }
                 }
                 serde_yaml::Value::Mapping(map) => {
+                    let field = |name: &str| map.get(serde_yaml::Value::String(name.to_string())).and_then(|v| v.as_str());
                     // Check for dropdown reference
-                    if let Some(dropdown_name) = map.get(&serde_yaml::Value::String("dropdown".to_string()))
-                        .and_then(|v| v.as_str())
+                    let entry = if let Some(dropdown_name) = field("dropdown") {
+                        Some(NavbarItem::Dropdown(dropdown_name.to_string()))
+                    }
+                    else if map.get(serde_yaml::Value::String("separator".to_string()))
+                        .and_then(|v| v.as_bool()) == Some(true)
                     {
-                        navbar_items.push(NavbarItem::Dropdown(dropdown_name.to_string()));
+                        Some(NavbarItem::Separator)
+                    }
+                    else if let Some(label) = field("label") {
+                        Some(NavbarItem::Label(label.to_string()))
+                    }
+                    // A page by name, optionally with its own link text: {page: "math", text: "Back to Math"}
+                    else if let Some(page_name) = field("page") {
+                        let matched = find_order_matches(markdown_files, page_name).into_iter().next();
+                        if matched.is_none() {
+                            log_warn!("navbar_order page '{}' doesn't match any page", page_name);
+                        }
+                        matched.map(|(_, relative_path, title)| {
+                            NavbarItem::MarkdownFile(relative_path.clone(), field("text").unwrap_or(title).to_string())
+                        })
                     }
                     // Check for external link
                     else {
-                        let url = map.get(&serde_yaml::Value::String("url".to_string()))
-                            .and_then(|v| v.as_str())
-                            .map(|s| s.to_string());
-                        let text = map.get(&serde_yaml::Value::String("text".to_string()))
-                            .and_then(|v| v.as_str())
-                            .map(|s| s.to_string());
-                        if let (Some(url), Some(text)) = (url, text) {
-                            navbar_items.push(NavbarItem::ExternalLink(url, text));
+                        match (field("url"), field("text")) {
+                            (Some(url), Some(text)) => Some(NavbarItem::ExternalLink(url.to_string(), text.to_string())),
+                            _ => None,
                         }
+                    };
+                    // show_on limits any entry to pages at a location, e.g. a "Back to section" link
+                    match (entry, field("show_on")) {
+                        (Some(entry), Some(show_on)) => navbar_items.push(NavbarItem::Conditional(show_on.to_string(), Box::new(entry))),
+                        (Some(entry), None) => navbar_items.push(entry),
+                        (None, _) => {}
                     }
                 }
                 _ => {}
             }
         }
-    } else if let Some(ref order) = page_order {
+    } else if let Some(ref order) = config.page_order {
         // Fall back to page_order if navbar_order not specified
         for item in order {
             match item {
                 serde_yaml::Value::String(page_name) => {
-                    // Simple string - find matching markdown file
-                    if let Some((_, relative_path, title)) = markdown_files.iter()
-                        .find(|(_, rel_path, _)| {
-                            let rel_key = rel_path.with_extension("")
-                                .to_string_lossy()
-                                .replace('\\', "/");
-                            rel_key == *page_name || rel_key.ends_with(&format!("/{}", page_name))
-                        })
-                        .cloned()
-                    {
-                        let rel_key = relative_path.with_extension("")
-                            .to_string_lossy()
-                            .replace('\\', "/");
+                    // Simple string or glob - find matching markdown files
+                    for (_, relative_path, title) in find_order_matches(markdown_files, page_name) {
+                        let rel_key = page_key(relative_path);
+                        if page_name.contains('*') && navbar_excluded(&rel_key) {
+                            continue;
+                        }
                         // Only add if not in dropdowns (but always include index)
                         if rel_key == "index" || !pages_in_dropdowns.contains(&rel_key) && !pages_in_dropdowns.contains(page_name) {
-                            navbar_items.push(NavbarItem::MarkdownFile(relative_path, title));
+                            navbar_items.push(NavbarItem::MarkdownFile(relative_path.clone(), title.clone()));
                         }
                     }
                 }
                 serde_yaml::Value::Mapping(map) => {
                     // Object with url and text fields
-                    let url = map.get(&serde_yaml::Value::String("url".to_string()))
+                    let url = map.get(serde_yaml::Value::String("url".to_string()))
                         .and_then(|v| v.as_str())
                         .map(|s| s.to_string());
-                    let text = map.get(&serde_yaml::Value::String("text".to_string()))
+                    let text = map.get(serde_yaml::Value::String("text".to_string()))
                         .and_then(|v| v.as_str())
                         .map(|s| s.to_string());
                     if let (Some(url), Some(text)) = (url, text) {
@@ -1065,71 +3980,1396 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
         // Add dropdowns at the end if using page_order
-        if let Some(ref dropdowns_map) = dropdowns {
+        if let Some(ref dropdowns_map) = config.dropdowns {
             for dropdown_name in dropdowns_map.keys() {
                 navbar_items.push(NavbarItem::Dropdown(dropdown_name.clone()));
             }
         }
     } else {
         // Default: use all markdown files (filtered), then dropdowns
-        for (_, relative_path, title) in &markdown_files {
+        for (_, relative_path, title) in markdown_files {
             let rel_key = relative_path.with_extension("")
                 .to_string_lossy()
                 .replace('\\', "/");
-            if rel_key == "index" || !pages_in_dropdowns.contains(&rel_key) {
+            if rel_key == "index" || !pages_in_dropdowns.contains(&rel_key) && !navbar_excluded(&rel_key) {
                 navbar_items.push(NavbarItem::MarkdownFile(relative_path.clone(), title.clone()));
             }
         }
         // Add dropdowns at the end
-        if let Some(ref dropdowns_map) = dropdowns {
+        if let Some(ref dropdowns_map) = config.dropdowns {
             for dropdown_name in dropdowns_map.keys() {
                 navbar_items.push(NavbarItem::Dropdown(dropdown_name.clone()));
             }
         }
     }
+    navbar_items
+}
 
-    // Build a HashSet of markdown file paths (without extension) for link conversion
-    let markdown_file_names: std::collections::HashSet<String> = markdown_files.iter()
-        .map(|(_, relative_path, _)| {
-            relative_path.with_extension("")
-                .to_string_lossy()
-                .replace('\\', "/")
-        })
+fn build_site(options: &BuildOptions) -> Result<BuildReport, Box<dyn std::error::Error>> {
+    let started = std::time::Instant::now();
+    let mut report = BuildReport::default();
+    let dist_dir = Path::new("dist");
+
+    // Load config file if it exists
+    let config = match options.config_path {
+        Some(ref path) => load_config(path)?,
+        None => load_default_config(&default_config_path()),
+    };
+    if config.absolute_links == Some(true) && config.site_url.is_none() {
+        return Err("absolute_links needs site_url to build absolute URLs from".into());
+    }
+
+    let content_dirs = resolve_content_dirs(options, &config);
+    let content_root_of = |full_path: &Path| content_root(&content_dirs, full_path);
+    let includes_dirs: Vec<PathBuf> = content_dirs.iter().map(|root| root.join(INCLUDES_DIR)).collect();
+    
+    // Create the default content directory if it doesn't exist
+    if content_dirs.len() == 1 && !content_dirs[0].exists() && !options.dry_run {
+        fs::create_dir_all(&content_dirs[0])?;
+    }
+    
+    // Create dist directory if it doesn't exist
+    if !dist_dir.exists() && !options.dry_run {
+        fs::create_dir_all(dist_dir)?;
+    }
+    
+    // Find all markdown files recursively (full_path, relative_path, title)
+    let (mut markdown_files, page_weights, colocated_assets) = discover_pages(&content_dirs, &mut report.pages_skipped)?;
+    let colocated_keys: std::collections::HashSet<String> = colocated_assets.iter()
+        .map(|(_, relative_path)| relative_path.to_string_lossy().replace('\\', "/"))
         .collect();
 
+    // Build a map of markdown file paths (without extension) to titles
+    // Use relative path as key (e.g., "math/sir" for "content/math/sir.md")
+    let mut markdown_titles: std::collections::HashMap<String, String> = std::collections::HashMap::new();
+    for (_, relative_path, title) in &markdown_files {
+        // Convert relative path to string key (without .md extension)
+        let key = relative_path.with_extension("")
+            .to_string_lossy()
+            .replace('\\', "/"); // Normalize path separators
+        markdown_titles.insert(key, title.clone());
+    }
+    if let Some(ref dropdowns) = config.dropdowns {
+        warn_missing_dropdown_pages(dropdowns, &markdown_titles);
+    }
+
+    // Sort markdown files according to config or alphabetically
+    if let Some(ref order) = config.page_order {
+        // Separate index from other pages
+        let mut index_file: Option<(PathBuf, PathBuf, String)> = None;
+        let mut other_files: Vec<(PathBuf, PathBuf, String)> = Vec::new();
+        
+        for file in markdown_files {
+            let relative_key = file.1.with_extension("")
+                .to_string_lossy()
+                .replace('\\', "/");
+            if relative_key == "index" {
+                index_file = Some(file);
+            } else {
+                other_files.push(file);
+            }
+        }
+        
+        // Sort other files according to config order
+        other_files.sort_by(|a, b| {
+            let a_key = a.1.with_extension("")
+                .to_string_lossy()
+                .replace('\\', "/");
+            let b_key = b.1.with_extension("")
+                .to_string_lossy()
+                .replace('\\', "/");
+            
+            // Check if config matches just the filename or the full path
+            let a_pos = order.iter().position(|x| {
+                x.as_str().map(|page_name| order_entry_matches(page_name, &a_key)).unwrap_or(false)
+            });
+            let b_pos = order.iter().position(|x| {
+                x.as_str().map(|page_name| order_entry_matches(page_name, &b_key)).unwrap_or(false)
+            });
+            
+            match (a_pos, b_pos) {
+                // Pages matched by the same glob keep alphabetical order
+                (Some(a_idx), Some(b_idx)) => a_idx.cmp(&b_idx).then_with(|| a_key.cmp(&b_key)),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => a_key.cmp(&b_key), // Alphabetical fallback for unlisted files
+            }
+        });
+        
+        // Reconstruct with index first
+        let mut sorted_files = Vec::new();
+        if let Some(index) = index_file {
+            sorted_files.push(index);
+        }
+        sorted_files.extend(other_files);
+        markdown_files = sorted_files;
+    } else {
+        // Default: index first, then by front matter weight (unweighted pages last),
+        // then alphabetically
+        markdown_files.sort_by(|a, b| {
+            let a_key = a.1.with_extension("")
+                .to_string_lossy()
+                .replace('\\', "/");
+            let b_key = b.1.with_extension("")
+                .to_string_lossy()
+                .replace('\\', "/");
+            let a_weight = page_weights.get(&a_key);
+            let b_weight = page_weights.get(&b_key);
+            
+            match (a_key == "index", b_key == "index") {
+                (true, false) => std::cmp::Ordering::Less,
+                (false, true) => std::cmp::Ordering::Greater,
+                _ => (a_weight.is_none(), a_weight, &a_key).cmp(&(b_weight.is_none(), b_weight, &b_key)),
+            }
+        });
+    }
+
+    // Filter out pages that are in sequence dropdowns (like Resources) from the regular navbar
+    // Pages in mapping dropdowns (like Syllabi) should still appear in navbar
+    let mut pages_in_dropdowns: std::collections::HashSet<String> = std::collections::HashSet::new();
+    if let Some(ref dropdowns_map) = config.dropdowns {
+        for dropdown_value in dropdowns_map.values() {
+            // For mappings (like Syllabi), we don't hide the pages - they're just for linking to syllabi
+            if let serde_yaml::Value::Sequence(seq) = dropdown_value {
+                // For sequences (like Resources), the items are page names that should be hidden
+                for item in seq {
+                    if let Some(page_name) = item.as_str() {
+                        pages_in_dropdowns.insert(page_name.to_string());
+                    }
+                }
+            }
+        }
+    }
+    
+    // Reading order for prev/next links: the page_order sequence without index, 404 or dropdown-only pages
+    let reading_order: Vec<(String, String)> = if config.page_order.is_some() {
+        markdown_files.iter()
+            .map(|(_, relative_path, title)| (page_key(relative_path), title.clone()))
+            .filter(|(key, _)| {
+                let file_name = key.rsplit('/').next().unwrap_or(key);
+                key != "index" && key != "404" && !pages_in_dropdowns.contains(key) && !pages_in_dropdowns.contains(file_name)
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let navbar_items = build_navbar_items(&config, &markdown_files, &pages_in_dropdowns);
+
+    // Map markdown page keys (without extension) to output keys for link conversion.
+    // Source names are included so links to a slugged page's original filename still resolve.
+    let mut markdown_file_names: std::collections::HashMap<String, String> = std::collections::HashMap::new();
+    for (full_path, _, _) in &markdown_files {
+        let source_key = page_key(full_path.strip_prefix(content_root_of(full_path)).unwrap_or(full_path));
+        markdown_file_names.insert(source_key.clone(), source_key);
+    }
+    let mut output_sources: std::collections::HashMap<String, (&PathBuf, bool)> = std::collections::HashMap::new();
+    for (full_path, relative_path, _) in &markdown_files {
+        let output_key = page_key(relative_path);
+        let source_key = page_key(full_path.strip_prefix(content_root_of(full_path)).unwrap_or(full_path));
+        let slugged = output_key != source_key;
+        if slugged {
+            markdown_file_names.insert(source_key, output_key.clone());
+        }
+        markdown_file_names.insert(output_key.clone(), output_key.clone());
+
+        // Two pages publishing to the same file where a slug is involved is an error;
+        // otherwise (e.g. a.md next to a.markdown) the later source wins, but never silently
+        if let Some((other, other_slugged)) = output_sources.insert(output_key.clone(), (full_path, slugged)) {
+            if slugged || other_slugged {
+                return Err(format!(
+                    "Slug collision: {} and {} both publish to {}.html",
+                    other.display(), full_path.display(), output_key
+                ).into());
+            }
+            log_warn!(
+                "{} and {} both publish to {}.html; {} will overwrite the other",
+                other.display(), full_path.display(), output_key, full_path.display()
+            );
+        }
+    }
+
+    let logo = resolve_site_image(config.logo.as_ref(), "assets/logo-wide.png");
+    let base_path = link_base_path(&config);
+    let root_path = site_root_path(&config);
+
+    // Content-hash cache of rendered pages, discarded whenever anything site-wide changes
+    let build_cache = (config.build_cache == Some(true)).then(|| {
+        let config_file = options.config_path.clone().unwrap_or_else(default_config_path);
+        let fingerprint = site_fingerprint(&generator_fingerprint(), &config_file, Path::new("assets"), &markdown_files, &includes_dirs);
+        BuildCache::load(dist_dir, &fingerprint)
+    });
+    let mut next_cache = build_cache.as_ref().map(|cache| BuildCache {
+        fingerprint: cache.fingerprint.clone(),
+        pages: std::collections::BTreeMap::new(),
+    });
+
+    // KaTeX output cache lives for this build only
+    let mut math = MathRenderer::new(
+        config.katex_macros.clone().unwrap_or_default(),
+        config.math_preserve_newlines == Some(true),
+        config.math_strict == Some(true) || options.strict,
+        MathDelimiters::from_config(config.math_delimiters.as_ref()),
+    );
+
+    // With --only the whole site is still scanned for the navbar and link map,
+    // but just the one page is rendered
+    let same_file = |a: &Path, b: &Path| {
+        let normal = |p: &Path| p.components().filter(|c| !matches!(c, std::path::Component::CurDir)).collect::<PathBuf>();
+        normal(a) == normal(b)
+    };
+    if let Some(ref only) = options.only {
+        if !markdown_files.iter().any(|(full_path, _, _)| same_file(full_path, only)) {
+            return Err(format!("--only: {} is not a page in {}", only.display(),
+                content_dirs.iter().map(|d| d.display().to_string()).collect::<Vec<_>>().join(", ")).into());
+        }
+    }
+
     // Process each markdown file
+    let mut search_entries: Vec<SearchEntry> = Vec::new();
+    let mut external_links: std::collections::BTreeMap<String, Vec<String>> = std::collections::BTreeMap::new();  // URL -> pages
+    let mut redirects: Vec<(String, String, String)> = Vec::new();  // (alias key, target key, title)
+    let mut sitemap_pages: Vec<String> = Vec::new();
+    let mut section_entries: Vec<SearchEntry> = Vec::new();  // Listing data for section indexes
     for (full_path, relative_path, title) in &markdown_files {
+        if options.only.as_ref().is_some_and(|only| !same_file(full_path, only)) {
+            continue;
+        }
         let content = fs::read_to_string(full_path)?;
-        let (_, markdown_content) = extract_frontmatter(&content);
-        let html_content = markdown_to_html(markdown_content, &markdown_file_names);
-        
+        let (frontmatter, markdown_content) = extract_frontmatter(&content);
+        let data_page = frontmatter.as_ref().and_then(|fm| fm.data.as_ref())
+            .map(|data| expand_data_template(markdown_content, data, full_path));
+        let markdown_content = data_page.as_deref().unwrap_or(markdown_content);
+        let raw_body = markdown_content;
+        let markdown_content = expand_includes(markdown_content, &includes_dirs, &mut vec![full_path.clone()])?;
+        let data_dir = config.data_dir.as_ref().map(PathBuf::from).unwrap_or_else(|| content_root_of(full_path));
+        let markdown_content = expand_tables(&markdown_content, &data_dir)?;
+        let (markdown_content, directives) = extract_directives(&markdown_content, full_path);
+        let markdown_content = resolve_wikilinks(&markdown_content, &markdown_titles, full_path);
+        let markdown_content = markdown_content.as_str();
+        let frontmatter = frontmatter.unwrap_or_default();
+
         let rel_key = relative_path.with_extension("")
             .to_string_lossy()
             .replace('\\', "/");
+        let page_path = format!("{}.html", rel_key);
+
+        // Hosts serve 404.html for any missing URL, so its links must be root-absolute
+        let is_404 = rel_key == "404";
+
+        // A redirect stub keeps its place in the navbar and page order but renders no content
+        if let Some(ref target) = frontmatter.redirect_to {
+            let target = target.trim();
+            let href = if is_external_url(target) || target.starts_with("mailto:") {
+                target.to_string()
+            } else {
+                let (target_base, fragment_query) = target.split_at(target.find(['#', '?']).unwrap_or(target.len()));
+                let stem = strip_markdown_extension(target_base)
+                    .or_else(|| target_base.strip_suffix(".html"))
+                    .unwrap_or(target_base);
+                let find_page = |name: &str| markdown_file_names.iter()
+                    .find(|(key, _)| key.as_str() == name || key.ends_with(&format!("/{}", name)))
+                    .map(|(_, output_key)| output_key);
+                let matched = find_page(stem).or_else(|| find_page(&format!("{}/index", stem.trim_end_matches('/'))));
+                match matched {
+                    Some(key) => match base_path {
+                        Some(ref base) => format!("{}{}.html{}", base, key, fragment_query),
+                        None => format!("{}{}", calculate_relative_link_path(relative_path, key), fragment_query),
+                    },
+                    None => {
+                        log_warn!("redirect_to '{}' in {} doesn't match any page", target, full_path.display());
+                        target.to_string()
+                    }
+                }
+            };
+            write_output(&dist_dir.join(relative_path.with_extension("html")), generate_redirect_html(&href, title))?;
+            report.pages_rendered += 1;
+            continue;
+        }
+        let link_base = if is_404 { root_path.clone() } else { base_path.clone().unwrap_or_default() };
+        // Preserve directory structure in dist. With build_cache, a page whose source and
+        // inputs hash the same as last time keeps the output already in dist/
+        let html_path = dist_dir.join(relative_path.with_extension("html"));
+        let last_updated = (config.last_updated == Some(true)).then(|| last_updated_date(full_path)).flatten();
+        let page_hash = build_cache.as_ref().map(|_| hash_hex(&[
+            content.as_bytes(),
+            markdown_content.as_bytes(),
+            last_updated.as_deref().unwrap_or_default().as_bytes(),
+        ]));
+        let cached = match (&build_cache, &page_hash) {
+            (Some(cache), Some(hash)) => cache.is_fresh(&page_path, hash, &html_path),
+            _ => false,
+        };
+        if let (Some(cache), Some(hash)) = (next_cache.as_mut(), page_hash) {
+            cache.pages.insert(page_path.clone(), hash);
+        }
+        if cached {
+            log_verbose!("Unchanged: {}", html_path.display());
+            report.pages_cached += 1;
+            if options.check_external_links {
+                collect_external_links(&fs::read_to_string(&html_path).unwrap_or_default(), &page_path, &mut external_links);
+            }
+        } else {
+            // Raw pages skip markdown, math and link rewriting; only the layout is added
+            let mut html_content = if frontmatter.raw == Some(true) || is_raw_html_source(full_path) {
+                raw_body.to_string()
+            } else {
+                markdown_to_html(markdown_content, &markdown_file_names, &mut math, &config, &directives, relative_path, &link_base)
+            };
+            let math_errors = math.take_errors();
+            if !math_errors.is_empty() && config.math_strict == Some(true) {
+                for (tex, message) in &math_errors {
+                    eprintln!("{}: math error in `{}`: {}", full_path.display(), tex, message);
+                }
+                return Err(format!("{} math error(s) in {} (math_strict is on)", math_errors.len(), full_path.display()).into());
+            }
+            // Under --strict alone they're warnings, failing the build once it finishes
+            for (tex, message) in &math_errors {
+                log_warn!("Math error in {}: `{}`: {}", full_path.display(), tex, message);
+            }
         
-        // Calculate asset prefix based on depth (e.g., "../" for one level deep)
-        let asset_prefix = calculate_asset_prefix(relative_path);
-        
-        // Generate navbar HTML with current page highlighted
-        let navbar = generate_navbar(&navbar_items, true, dropdowns.as_ref(), &markdown_titles, Some(&rel_key), &asset_prefix);
+            // Calculate asset prefix based on depth (e.g., "../" for one level deep),
+            // or use the deployment base path so URLs are absolute from the site root
+            let asset_prefix = if is_404 {
+                root_path.clone()
+            } else {
+                page_asset_prefix(relative_path, base_path.as_deref())
+            };
         
-        let html_output = generate_html(title, &html_content, &navbar, &asset_prefix)?;
+            if config.page_images == Some(true) {
+                let page_dir = relative_path.parent().unwrap_or(Path::new(""));
+                let (rewritten, copied) = rewrite_page_images(&html_content, page_dir, &content_root_of(full_path), dist_dir, &asset_prefix)?;
+                html_content = rewritten;
+                report.assets_copied += copied;
+            }
+            if !colocated_keys.is_empty() {
+                let page_dir = relative_path.parent().unwrap_or(Path::new(""));
+                html_content = rewrite_colocated_refs(&html_content, page_dir, &colocated_keys, &asset_prefix);
+            }
+
+            // Generate navbar HTML with current page highlighted, unless the page opts out
+            let navbar = if frontmatter.navbar == Some(false) || directives.no_navbar {
+                String::new()
+            } else {
+                generate_navbar(&navbar_items, true, config.dropdowns.as_ref(), &markdown_titles, Some(&rel_key), &asset_prefix, logo.as_deref())
+            };
         
-        // Preserve directory structure in dist
-        let html_path = dist_dir.join(relative_path.with_extension("html"));
+            let neighbor = |index: usize| {
+                let (key, title) = &reading_order[index];
+                let href = match base_path {
+                    Some(ref base) => format!("{}{}.html", base, key),
+                    None => calculate_relative_link_path(relative_path, key),
+                };
+                (title.clone(), href)
+            };
+            let position = reading_order.iter().position(|(key, _)| *key == rel_key);
+            let prev_link = position.and_then(|i| i.checked_sub(1)).map(neighbor);
+            let next_link = position.map(|i| i + 1).filter(|&i| i < reading_order.len()).map(neighbor);
+            let reading_time = (config.reading_time == Some(true))
+                .then(|| reading_time_minutes(word_count(markdown_content, &config)));
+            let mut html_output = generate_html(&PageContext {
+                title,
+                content: &html_content,
+                navbar: &navbar,
+                asset_prefix: &asset_prefix,
+                page_path: &page_path,
+                frontmatter: &frontmatter,
+                reading_time,
+                last_updated: last_updated.as_deref(),
+                prev: prev_link.as_ref().map(|(title, href)| (title.as_str(), href.as_str())),
+                next: next_link.as_ref().map(|(title, href)| (title.as_str(), href.as_str())),
+            }, &config)?;
+            if config.minify == Some(true) {
+                let minified = minify_page(&html_output);
+                report.bytes_saved += html_output.len().saturating_sub(minified.len());
+                html_output = minified;
+            }
         
-        // Create parent directories if they don't exist
-        if let Some(parent) = html_path.parent() {
-            fs::create_dir_all(parent)?;
+            if options.check_external_links {
+                collect_external_links(&html_output, &page_path, &mut external_links);
+            }
+            write_output(&html_path, html_output)?;
+            report.pages_rendered += 1;
         }
-        
-        fs::write(&html_path, html_output)?;
-        println!("Generated: {}", html_path.display());
+
+        if !is_404 && frontmatter.noindex != Some(true) {
+            sitemap_pages.push(page_path.clone());
+        }
+
+        for alias in frontmatter.aliases.iter().flatten() {
+            let alias_key = alias.trim().trim_start_matches('/').trim_end_matches(".html");
+            let alias_key = strip_markdown_extension(alias_key).unwrap_or(alias_key);
+            redirects.push((alias_key.to_string(), rel_key.clone(), title.clone()));
+        }
+
+        if config.search_index == Some(true) && !directives.no_search && !is_404 {
+            search_entries.push(SearchEntry {
+                title: title.clone(),
+                url: relative_path.with_extension("html").to_string_lossy().replace('\\', "/"),
+                excerpt: excerpt(markdown_content, 200, &config),
+                tags: frontmatter.tags.clone().unwrap_or_default(),
+            });
+        }
+
+        if config.section_indexes == Some(true) {
+            section_entries.push(SearchEntry {
+                title: title.clone(),
+                url: page_path.clone(),
+                excerpt: excerpt(markdown_content, 200, &config),
+                tags: frontmatter.tags.clone().unwrap_or_default(),
+            });
+        }
+    }
+
+    if options.check_external_links {
+        check_external_links(&external_links);
+    }
+
+    // Site-wide outputs and assets are left as they are for a single-page build
+    if options.only.is_some() {
+        report.elapsed = started.elapsed();
+        return Ok(report);
+    }
+    if let Some(ref cache) = next_cache {
+        cache.save(dist_dir)?;
     }
 
+    // Generate listings for directories that don't provide their own index page
+    let mut sections: std::collections::BTreeMap<&str, Vec<&SearchEntry>> = std::collections::BTreeMap::new();
+    for entry in &section_entries {
+        if let Some((dir, _)) = entry.url.rsplit_once('/') {
+            sections.entry(dir).or_default().push(entry);
+        }
+    }
+    for (dir, entries) in &sections {
+        let index_key = format!("{}/index", dir);
+        if markdown_file_names.values().any(|key| *key == index_key) {
+            continue;
+        }
+        // With paginate, long listings continue at dir/page/2/index.html, dir/page/3/...
+        let per_page = config.paginate.filter(|&n| n > 0).unwrap_or(entries.len()).max(1);
+        let chunks: Vec<&[&SearchEntry]> = entries.chunks(per_page).collect();
+        let listing_key = |number: usize| {
+            if number == 1 { index_key.clone() } else { format!("{}/page/{}/index", dir, number) }
+        };
+        let section = section_title(dir);
+        for (i, chunk) in chunks.iter().enumerate() {
+            let number = i + 1;
+            let page_path = format!("{}.html", listing_key(number));
+            let asset_prefix = match base_path {
+                Some(ref base) => base.clone(),
+                None => calculate_asset_prefix(Path::new(&page_path)),
+            };
+            let link = |number: usize| {
+                let label = format!("Page {}", number);
+                match base_path {
+                    Some(ref base) => (label, format!("{}{}.html", base, listing_key(number))),
+                    None => (label, calculate_relative_link_path(Path::new(&page_path), &listing_key(number))),
+                }
+            };
+            let prev_link = (number > 1).then(|| link(number - 1));
+            let next_link = (number < chunks.len()).then(|| link(number + 1));
+            let title = if chunks.len() > 1 {
+                format!("{} (page {} of {})", section, number, chunks.len())
+            } else {
+                section.clone()
+            };
+            let link_prefix = if number == 1 { "" } else { "../../" };
+            let navbar = generate_navbar(&navbar_items, true, config.dropdowns.as_ref(), &markdown_titles, Some(&index_key), &asset_prefix, logo.as_deref());
+            let mut html_output = generate_html(&PageContext {
+                title: &title,
+                content: &render_section_index(&title, chunk, link_prefix),
+                navbar: &navbar,
+                asset_prefix: &asset_prefix,
+                page_path: &page_path,
+                frontmatter: &FrontMatter::default(),
+                reading_time: None,
+                last_updated: None,
+                prev: prev_link.as_ref().map(|(title, href)| (title.as_str(), href.as_str())),
+                next: next_link.as_ref().map(|(title, href)| (title.as_str(), href.as_str())),
+            }, &config)?;
+            if config.minify == Some(true) {
+                let minified = minify_page(&html_output);
+                report.bytes_saved += html_output.len().saturating_sub(minified.len());
+                html_output = minified;
+            }
+            write_output(&dist_dir.join(&page_path), html_output)?;
+            report.pages_rendered += 1;
+            sitemap_pages.push(page_path);
+        }
+    }
+
+    if config.default_404 == Some(true) && !markdown_file_names.values().any(|key| key == "404") {
+        let navbar = generate_navbar(&navbar_items, true, config.dropdowns.as_ref(), &markdown_titles, None, &root_path, logo.as_deref());
+        let content = format!(
+            "<h1>Page not found</h1>\n<p>The page you were looking for doesn't exist. <a href=\"{}index.html\">Return to the home page</a>.</p>\n",
+            root_path
+        );
+        let mut html_output = generate_html(&PageContext {
+            title: "Page not found",
+            content: &content,
+            navbar: &navbar,
+            asset_prefix: &root_path,
+            page_path: "404.html",
+            frontmatter: &FrontMatter::default(),
+            reading_time: None,
+            last_updated: None,
+            prev: None,
+            next: None,
+        }, &config)?;
+        if config.minify == Some(true) {
+            let minified = minify_page(&html_output);
+            report.bytes_saved += html_output.len().saturating_sub(minified.len());
+            html_output = minified;
+        }
+        write_output(&dist_dir.join("404.html"), html_output)?;
+        report.pages_rendered += 1;
+    }
+
+    // Write redirect stubs for page aliases, refusing to shadow real pages or each other
+    let mut alias_keys: std::collections::HashSet<&str> = std::collections::HashSet::new();
+    for (alias_key, target_key, title) in &redirects {
+        if markdown_file_names.values().any(|key| key == alias_key) {
+            return Err(format!("Alias '{}' for {} collides with an existing page", alias_key, target_key).into());
+        }
+        if !alias_keys.insert(alias_key) {
+            return Err(format!("Alias '{}' is declared by more than one page", alias_key).into());
+        }
+        let alias_path = PathBuf::from(format!("{}.html", alias_key));
+        let target = match base_path {
+            Some(ref base) => format!("{}{}.html", base, target_key),
+            None => calculate_relative_link_path(&alias_path, target_key),
+        };
+        write_output(&dist_dir.join(&alias_path), generate_redirect_html(&target, title))?;
+    }
+
+    // The sitemap needs absolute URLs, so it's only written when site_url is known
+    if let Some(ref site_url) = config.site_url {
+        write_sitemap(dist_dir, site_url, &sitemap_pages)?;
+    }
+    write_robots_txt(dist_dir, config.site_url.as_deref())?;
+
+    // Write the client-side search index (URLs are relative to the site root)
+    if config.search_index == Some(true) {
+        write_output(&dist_dir.join("search-index.json"), serde_json::to_string_pretty(&search_entries)?)?;
+    }
+
+    // Files colocated with the pages keep their place in the tree; a generated page wins over
+    // a same-named .html file. Top-level assets/ is copied last, as before.
+    report.assets_copied += timed(Phase::Assets, || -> Result<usize, Box<dyn std::error::Error>> {
+        let mut copied = 0;
+        for (full_path, relative_path) in &colocated_assets {
+            let is_html = relative_path.extension().is_some_and(|ext| ext == "html");
+            if is_html && markdown_file_names.values().any(|key| *key == page_key(relative_path)) {
+                log_warn!("{} is shadowed by a generated page and was not copied", full_path.display());
+                continue;
+            }
+            copy_output(full_path, &dist_dir.join(relative_path))?;
+            copied += 1;
+        }
+        Ok(copied)
+    })?;
+
     // Copy assets to dist after building
-    copy_assets_to_dist()?;
+    report.assets_copied += timed(Phase::Assets, copy_assets_to_dist)?;
 
-    Ok(())
+    if let Some(ref algorithms) = config.precompress {
+        let (files, bytes) = timed(Phase::Assets, || precompress_outputs(algorithms, config.precompress_assets == Some(true)))?;
+        report.files_compressed += files;
+        report.bytes_compressed += bytes;
+    }
+
+    report.elapsed = started.elapsed();
+    Ok(report)
 }
 
+// Extensions worth compressing when precompress_assets is on; images and fonts already are
+const COMPRESSIBLE_EXTENSIONS: &[&str] = &["css", "js", "json", "svg", "xml", "txt", "map"];
+
+// An algorithm listed in precompress
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Precompression {
+    Gzip,
+    Brotli,
+}
+
+impl Precompression {
+    fn from_config(name: &str) -> Option<Self> {
+        match name.trim() {
+            "gzip" | "gz" => Some(Precompression::Gzip),
+            "brotli" | "br" => Some(Precompression::Brotli),
+            _ => None,
+        }
+    }
+
+    fn suffix(self) -> &'static str {
+        match self {
+            Precompression::Gzip => "gz",
+            Precompression::Brotli => "br",
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        // Highest levels for both: the work happens once per build, not per request.
+        // The gzip header carries no timestamp, so output is reproducible.
+        match self {
+            Precompression::Gzip => {
+                use std::io::Write;
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Precompression::Brotli => {
+                let params = brotli::enc::BrotliEncoderParams { quality: 11, ..Default::default() };
+                let mut output = Vec::new();
+                brotli::BrotliCompress(&mut &data[..], &mut output, &params)?;
+                Ok(output)
+            }
+        }
+    }
+}
+
+fn precompress_outputs(algorithms: &[String], include_assets: bool) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+    // Pages (and text assets) written this build get a .gz/.br sibling for hosts that serve
+    // precompressed files
+    let mut selected: Vec<Precompression> = Vec::new();
+    for name in algorithms {
+        match Precompression::from_config(name) {
+            Some(algorithm) if !selected.contains(&algorithm) => selected.push(algorithm),
+            Some(_) => {}
+            None => {
+                log_warn!("Unknown precompress algorithm '{}', expected gzip or brotli", name.trim());
+            }
+        }
+    }
+    let written = OUTPUT_FILES.lock().map(|list| list.clone()).unwrap_or_default();
+    let mut targets: Vec<PathBuf> = written.iter()
+        .map(PathBuf::from)
+        .filter(|path| {
+            let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+            ext == "html" || include_assets && COMPRESSIBLE_EXTENSIONS.contains(&ext)
+        })
+        .collect();
+    targets.sort();
+    targets.dedup();
+    if is_dry_run() {
+        return Ok((0, 0));
+    }
+
+    let (mut files, mut bytes) = (0, 0);
+    for target in &targets {
+        let contents = fs::read(target)?;
+        for algorithm in &selected {
+            let compressed = algorithm.compress(&contents)?;
+            let mut compressed_path = target.clone().into_os_string();
+            compressed_path.push(format!(".{}", algorithm.suffix()));
+            bytes += compressed.len();
+            write_output(Path::new(&compressed_path), compressed)?;
+            files += 1;
+        }
+    }
+    Ok((files, bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn render(markdown: &str, config: &Config) -> String {
+        let mut math = MathRenderer::new(HashMap::new(), false, false, MathDelimiters::from_config(None));
+        markdown_to_html(markdown, &HashMap::new(), &mut math, config, &Directives::default(), Path::new("page.md"), "")
+    }
+
+    fn links(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(key, target)| (key.to_string(), target.to_string())).collect()
+    }
+
+    // A scratch content directory, removed again when the test drops it
+    struct Fixture(PathBuf);
+
+    impl Fixture {
+        fn new(name: &str, files: &[(&str, &str)]) -> Self {
+            let root = std::env::temp_dir().join(format!("ideeep-test-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+            for (path, contents) in files {
+                let path = root.join(path);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, contents).unwrap();
+            }
+            Fixture(root)
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn discover(root: &Path) -> (Vec<String>, Vec<String>) {
+        // (page keys, colocated asset paths) in discovery order
+        let mut skipped = 0;
+        let (pages, _, assets) = discover_pages(&[root.to_path_buf()], &mut skipped).unwrap();
+        (
+            pages.iter().map(|(_, relative_path, _)| page_key(relative_path)).collect(),
+            assets.iter().map(|(_, relative_path)| relative_path.to_string_lossy().replace('\\', "/")).collect(),
+        )
+    }
+
+    #[test]
+    fn discovery_order_is_sorted() {
+        let fixture = Fixture::new("order", &[
+            ("zeta.md", "z"), ("alpha.md", "a"), ("math/sir.md", "s"), ("math/anova.md", "a"), ("index.md", "i"),
+        ]);
+        let (pages, _) = discover(&fixture.0);
+        assert_eq!(pages, ["alpha", "index", "math/anova", "math/sir", "zeta"]);
+    }
+
+    #[test]
+    fn accepts_each_markdown_extension() {
+        for ext in MARKDOWN_EXTENSIONS {
+            assert!(is_markdown_file(Path::new(&format!("notes/sir.{}", ext))));
+            assert_eq!(strip_markdown_extension(&format!("notes/sir.{}", ext)), Some("notes/sir"));
+        }
+        assert!(!is_markdown_file(Path::new("notes/sir.txt")));
+        assert_eq!(strip_markdown_extension("sir.html"), None);
+
+        let fixture = Fixture::new("extensions", &[
+            ("a.md", "a"), ("b.markdown", "b"), ("c.mdown", "c"), ("d.mkd", "d"), ("e.txt", "e"),
+        ]);
+        let (pages, assets) = discover(&fixture.0);
+        assert_eq!(pages, ["a", "b", "c", "d"]);
+        assert_eq!(assets, ["e.txt"]);
+    }
+
+    #[test]
+    fn front_matter_may_end_the_file() {
+        assert_eq!(split_frontmatter("---\ntitle: X\n---", "---"), Some(("title: X\n", "")));
+        assert_eq!(split_frontmatter("---\r\ntitle: X\r\n---\r\nbody", "---"), Some(("title: X\r\n", "body")));
+        let (frontmatter, body) = extract_frontmatter("---\ntitle: Ends here\n---");
+        assert_eq!(frontmatter.and_then(|fm| fm.title).as_deref(), Some("Ends here"));
+        assert_eq!(body, "");
+        let (frontmatter, body) = extract_frontmatter("---\ntitle: X\n---\nbody");
+        assert!(frontmatter.is_some());
+        assert_eq!(body, "body");
+        // "---" only closes the block on a line of its own
+        assert!(extract_frontmatter("---\ntitle: X ---").0.is_none());
+    }
+
+    #[test]
+    fn excerpt_is_plain_prose() {
+        let markdown = "# Intro\n\nSome *emphasis* and `code`.\n\n```rust\nfn hidden() {}\n```\n\n<div>raw</div>\n\n- one\n- two\n";
+        assert_eq!(excerpt(markdown, 200, &Config::default()), "Intro Some emphasis and code. one two");
+        assert_eq!(excerpt("alpha beta gamma delta", 12, &Config::default()), "alpha beta…");
+        // Admonition markers are dropped, ordinary bracketed text is kept
+        assert_eq!(excerpt("> [!NOTE]\n> Read this first.\n\n> [!TIP] Inline tip.\n\nSee [1] and [!NOTE].\n", 200, &Config::default()),
+                   "Read this first. Inline tip. See [1] and [!NOTE].");
+        // Shortcodes come out as they do on the page
+        let emoji: Config = serde_yaml::from_str("emoji: true\n").unwrap();
+        assert_eq!(excerpt("Launch :rocket: at 10:30:00, `:rocket:`\n", 200, &emoji), "Launch 🚀 at 10:30:00, :rocket:");
+        assert_eq!(excerpt("Launch :rocket:\n", 200, &Config::default()), "Launch :rocket:");
+        let abbreviations: Config = serde_yaml::from_str("abbreviations: true\n").unwrap();
+        assert_eq!(excerpt("Write HTML.\n\n*[HTML]: HyperText Markup Language\n", 200, &abbreviations), "Write HTML.");
+    }
+
+    #[test]
+    fn mixed_task_list() {
+        let html = render("- [x] done\n- [ ] todo\n- plain item\n", &Config::default());
+        assert!(html.contains("<ul class=\"task-list\">"));
+        assert_eq!(html.matches("<li class=\"task-list-item\"><input class=\"task-list-checkbox\" disabled=\"\"").count(), 2);
+        assert!(html.contains("checked=\"\""));
+        assert!(html.contains("<li>plain item</li>"));
+    }
+
+    #[test]
+    fn definition_list() {
+        let html = render("Term\n: First definition\n: Second definition\n", &Config::default());
+        assert!(html.contains("<dl>\n<dt>Term</dt>\n<dd>First definition</dd>\n<dd>Second definition</dd>\n</dl>"));
+    }
+
+    #[test]
+    fn base_path_urls_from_a_nested_page() {
+        for configured in ["/site/", "site", "/site", "site/"] {
+            assert_eq!(normalize_base_path(configured), "/site/");
+        }
+        assert_eq!(normalize_base_path("https://example.com/site"), "https://example.com/site/");
+
+        // tutorials/deep/page.md is two levels down, but every URL starts from the base path
+        let config: Config = serde_yaml::from_str("base_path: /site/\n").unwrap();
+        let base = config.base_path.as_deref().map(normalize_base_path);
+        let asset_prefix = page_asset_prefix(Path::new("tutorials/deep/page.md"), base.as_deref());
+        assert_eq!(asset_prefix, "/site/");
+
+        let files = links(&[("index", "index"), ("math/sir", "math/sir")]);
+        let content = markdown_to_html("[Home](index) and [SIR](sir#results)\n", &files, &mut MathRenderer::new(HashMap::new(), false, false, MathDelimiters::from_config(None)), &config, &Directives::default(), Path::new("tutorials/deep/page.md"), base.as_deref().unwrap_or_default());
+        let items = [NavbarItem::MarkdownFile(PathBuf::from("math/sir.md"), "SIR".to_string())];
+        let navbar = generate_navbar(&items, true, None, &HashMap::new(), Some("tutorials/deep/page"), &asset_prefix, Some("assets/logo-wide.png"));
+        let page = generate_html(&PageContext {
+            title: "Page",
+            content: &content,
+            navbar: &navbar,
+            asset_prefix: &asset_prefix,
+            page_path: "tutorials/deep/page.html",
+            frontmatter: &FrontMatter::default(),
+            reading_time: None,
+            last_updated: None,
+            prev: None,
+            next: None,
+        }, &config).unwrap();
+        assert!(page.contains("<link rel=\"stylesheet\" href=\"/site/assets/styles.css\""));
+        assert!(page.contains("<img src=\"/site/assets/logo-wide.png\""));
+        assert!(page.contains("<a href=\"/site/index.html\">Home</a> and <a href=\"/site/math/sir.html#results\">SIR</a>"));
+        assert!(page.contains("href=\"/site/math/sir.html\" class=\"nav-link\""));
+        assert!(!page.contains("\"../"));
+    }
+
+    #[test]
+    fn navbar_can_be_left_out() {
+        let frontmatter: FrontMatter = serde_yaml::from_str("navbar: false").unwrap();
+        assert_eq!(frontmatter.navbar, Some(false));
+        let page = |navbar| PageContext {
+            title: "Landing",
+            content: "<p>Hello</p>",
+            navbar,
+            asset_prefix: "",
+            page_path: "landing.html",
+            frontmatter: &frontmatter,
+            reading_time: None,
+            last_updated: None,
+            prev: None,
+            next: None,
+        };
+        let without = generate_html(&page(""), &Config::default()).unwrap();
+        assert!(!without.contains("<nav"));
+        assert!(without.contains("<div id=\"content\"") && without.contains("<p>Hello</p>"));
+        let with = generate_html(&page("<nav class=\"navbar\"></nav>"), &Config::default()).unwrap();
+        assert!(with.contains("<nav class=\"navbar\">"));
+    }
+
+    #[test]
+    fn smart_punctuation_skips_code() {
+        let html = render("\"Quoted\" -- and --- so on...\n\n`\"code\" -- here`\n", &Config::default());
+        assert!(html.contains("\u{201c}Quoted\u{201d} \u{2013} and \u{2014} so on\u{2026}"));
+        assert!(html.contains("<code>&quot;code&quot; -- here</code>"));
+        // Rendered math goes in after smart punctuation, so TeX like `--` stays as written
+        let html = render("$a -- b$ \"and\"\n", &Config::default());
+        assert!(html.contains("class=\"katex\"") && html.contains("\u{201c}and\u{201d}"));
+        assert!(!html.contains('\u{2013}'));
+    }
+
+    #[test]
+    fn toml_front_matter() {
+        let (frontmatter, body) = extract_frontmatter("+++\ntitle = \"From Hugo\"\ntags = [\"sir\", \"models\"]\n+++\nBody\n");
+        let frontmatter = frontmatter.unwrap();
+        assert_eq!(frontmatter.title.as_deref(), Some("From Hugo"));
+        assert_eq!(frontmatter.tags.unwrap(), ["sir", "models"]);
+        assert_eq!(body, "Body\n");
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_match("tutorials/*", "tutorials/intro"));
+        assert!(!glob_match("tutorials/*", "tutorials/advanced/intro"));
+        assert!(glob_match("tutorials/**", "tutorials/advanced/intro"));
+        assert!(glob_match("**/draft-*", "blog/2024/draft-one"));
+        assert!(glob_match("week-?", "week-1"));
+        assert!(!glob_match("week-?", "week-10"));
+    }
+
+    #[test]
+    fn order_mixes_globs_and_names() {
+        let pages: Vec<(PathBuf, PathBuf, String)> = ["tutorials/b.md", "intro.md", "tutorials/a.md", "math/sir.md"].iter()
+            .map(|p| (PathBuf::from(p), PathBuf::from(p), p.to_string()))
+            .collect();
+        let keys = |entry: &str| -> Vec<String> {
+            find_order_matches(&pages, entry).iter().map(|(_, rel, _)| page_key(rel)).collect()
+        };
+        assert_eq!(keys("tutorials/*"), ["tutorials/a", "tutorials/b"]);
+        assert_eq!(keys("sir"), ["math/sir"]);
+        assert_eq!(keys("intro"), ["intro"]);
+        assert!(keys("missing").is_empty());
+        assert!(order_entry_matches("math/sir", "math/sir"));
+        assert!(!order_entry_matches("sir", "math/sirs"));
+    }
+
+    #[test]
+    fn bare_links_keep_fragment_and_query() {
+        let files = links(&[("math/sir", "math/sir")]);
+        let convert = |href: &str| convert_internal_links(&format!("<a href=\"{}\">x</a>", href), &files, "", Path::new("index.md"));
+        assert_eq!(convert("sir#results"), "<a href=\"math/sir.html#results\">x</a>");
+        assert_eq!(convert("sir?x=1"), "<a href=\"math/sir.html?x=1\">x</a>");
+        assert_eq!(convert("sir?x=1#results"), "<a href=\"math/sir.html?x=1#results\">x</a>");
+        assert_eq!(convert("#local"), "<a href=\"#local\">x</a>");
+        assert_eq!(convert("https://example.com/sir"), "<a href=\"https://example.com/sir\">x</a>");
+    }
+
+    #[test]
+    fn front_matter_or_thematic_break() {
+        // A blank line after the opening fence is still front matter
+        let (frontmatter, body) = extract_frontmatter("---\n\ntitle: X\n---\nBody\n");
+        assert_eq!(frontmatter.and_then(|fm| fm.title).as_deref(), Some("X"));
+        assert_eq!(body, "Body\n");
+
+        // A leading rule with no closing fence, or with prose up to the next rule
+        for content in ["---\n\n## Intro\n\nText\n", "---\nJust prose here\n---\nMore\n", "---\n- a list\n---\n"] {
+            let (frontmatter, body) = extract_frontmatter(content);
+            assert!(frontmatter.is_none(), "{:?}", content);
+            assert_eq!(body, content);
+            assert!(matches!(parse_frontmatter(content), Ok(None)), "{:?}", content);
+        }
+        let html = render(extract_frontmatter("---\n\n## Intro\n").1, &Config::default());
+        assert_eq!(html, "<hr />\n<h2 id=\"intro\">Intro</h2>\n");
+
+        // The opening fence has to be the first line, and fences are whole lines
+        assert_eq!(split_frontmatter("\n---\ntitle: X\n---\n", "---"), None);
+        assert_eq!(split_frontmatter("---\ntitle: X ---\nbody\n", "---"), None);
+        assert!(extract_frontmatter("---\n---\nBody").0.is_some());
+
+        // Malformed front matter is still reported rather than passed off as a rule
+        assert!(parse_frontmatter("---\ntitle: a: b\n---\n").is_err());
+    }
+
+    #[test]
+    fn katex_macros_expand() {
+        let macros = HashMap::from([("\\RR".to_string(), "\\mathbb{R}".to_string())]);
+        let mut math = MathRenderer::new(macros, false, false, MathDelimiters::from_config(None));
+        let inline = math.render("x \\in \\RR", false).unwrap();
+        let display = math.render("\\RR^n", true).unwrap();
+        for html in [inline, display] {
+            assert!(html.contains("mathbb"));
+            assert!(!html.contains("katex-error"));
+        }
+    }
+
+    #[test]
+    fn multi_line_display_math_is_a_block() {
+        let markdown = "Before\n\n$$\n\\begin{pmatrix}\na & b \\\\\nc & d\n\\end{pmatrix}\n$$\n\nAfter\n";
+        let html = render(markdown, &Config::default());
+        assert!(html.contains("<div class=\"math-display\"><span class=\"katex-display\">"));
+        assert!(!html.contains("<p><div") && !html.contains("<p><span class=\"katex-display\">"));
+        assert!(html.contains("<p>Before</p>") && html.contains("<p>After</p>"));
+    }
+
+    #[test]
+    fn relative_links_between_directories() {
+        let link = |from: &str, to: &str| calculate_relative_link_path(Path::new(from), to);
+        assert_eq!(link("math/a.md", "programming/b"), "../programming/b.html");
+        assert_eq!(link("math/a.md", "math/b"), "b.html");
+        assert_eq!(link("math/a.md", "index"), "../index.html");
+        assert_eq!(link("index.md", "math/sir"), "math/sir.html");
+        assert_eq!(link("a/b/c.md", "a/d/e"), "../d/e.html");
+        assert_eq!(link("a/b/c.md", "x"), "../../x.html");
+        assert_eq!(link("a.md", "a/b/c"), "a/b/c.html");
+    }
+
+    #[test]
+    fn not_found_page_links_are_root_absolute() {
+        // 404.html is served at whatever URL was missing, so its .md links can't be relative
+        let files = links(&[("index", "index"), ("math/sir", "math/sir"), ("math/old-name", "math/new-name")]);
+        let convert = |href: &str, config: &Config| convert_internal_links(
+            &format!("<a href=\"{}\">x</a>", href), &files, &site_root_path(config), Path::new("404.md"),
+        );
+        let config = Config::default();
+        assert_eq!(convert("index.md", &config), "<a href=\"/index.html\">x</a>");
+        assert_eq!(convert("math/sir.md#results", &config), "<a href=\"/math/sir.html#results\">x</a>");
+        assert_eq!(convert("math/old-name.md", &config), "<a href=\"/math/new-name.html\">x</a>");
+        let config: Config = serde_yaml::from_str("site_url: https://example.github.io/course\n").unwrap();
+        assert_eq!(convert("index.md", &config), "<a href=\"/course/index.html\">x</a>");
+    }
+
+    #[test]
+    fn ignore_rules() {
+        let rules = IgnoreRules::parse("# comment\ndrafts/\n*.tmp.md\n!keep.tmp.md\n/notes/private.md\n");
+        assert!(rules.is_ignored("drafts", true));
+        assert!(rules.is_ignored("blog/drafts", true));
+        assert!(!rules.is_ignored("drafts", false));
+        assert!(rules.is_ignored("math/scratch.tmp.md", false));
+        assert!(!rules.is_ignored("keep.tmp.md", false));
+        assert!(rules.is_ignored("notes/private.md", false));
+        assert!(!rules.is_ignored("other/notes/private.md", false));
+        assert!(!rules.is_ignored("math/sir.md", false));
+    }
+
+    #[test]
+    fn markdown_options_toggle_extensions() {
+        let markdown = "| a | b |\n|---|---|\n| 1 | 2 |\n\n~~gone~~ \"quoted\"\n";
+        let all_on = render(markdown, &Config::default());
+        assert!(all_on.contains("<table>"));
+        assert!(all_on.contains("<del>gone</del>"));
+        assert!(all_on.contains("\u{201c}quoted\u{201d}"));
+
+        let config: Config = serde_yaml::from_str("markdown:\n  tables: false\n  strikethrough: false\n  smart_punctuation: false\n").unwrap();
+        let some_off = render(markdown, &config);
+        assert!(!some_off.contains("<table>"));
+        assert!(some_off.contains("~~gone~~"));
+        assert!(some_off.contains("&quot;quoted&quot;"));
+
+        // Excerpts and word counts read the markdown the same way the page does
+        assert_eq!(excerpt(markdown, 200, &Config::default()), "a b 1 2 gone \u{201c}quoted\u{201d}");
+        assert_eq!(excerpt(markdown, 200, &config), "| a | b | |---|---| | 1 | 2 | ~~gone~~ \"quoted\"");
+        let table = "| a | b |\n|---|---|\n| 1 | 2 |\n";
+        assert_eq!(word_count(table, &Config::default()), 4);
+        assert_eq!(word_count(table, &config), 11);
+    }
+
+    #[test]
+    fn currency_is_not_math() {
+        let mut math = MathRenderer::new(HashMap::new(), false, false, MathDelimiters::from_config(None));
+        let mut preprocess = |md: &str| {
+            let out = preprocess_math(md, &mut math);
+            let rendered = math.pending.len();
+            (math.restore(&out), rendered)
+        };
+        assert_eq!(preprocess("$5 and $10"), ("$5 and $10".to_string(), 0));
+        assert_eq!(preprocess("it costs $5 today and $x tomorrow"), ("it costs $5 today and $x tomorrow".to_string(), 0));
+
+        // Currency and real math on the same line
+        let (html, rendered) = preprocess("costs $5 today and $x$ tomorrow");
+        assert_eq!(rendered, 1);
+        assert!(html.starts_with("costs $5 today and <span class=\"katex\">"));
+        assert!(html.ends_with("</span> tomorrow"));
+        let (html, rendered) = preprocess("$x$ costs $5 and $10");
+        assert_eq!(rendered, 1);
+        assert!(html.ends_with("</span> costs $5 and $10"));
+
+        // A digit-led span without trailing space is still math
+        assert_eq!(preprocess("$5x$").1, 1);
+        assert_eq!(preprocess("$2^{10}$ and $3$").1, 2);
+    }
+
+    #[test]
+    fn directory_links_resolve_to_index() {
+        let files = links(&[("docs/index", "docs/index"), ("index", "index")]);
+        let convert = |href: &str| convert_internal_links(&format!("<a href=\"{}\">docs</a>", href), &files, "", Path::new("index.md"));
+        assert_eq!(convert("docs"), "<a href=\"docs/index.html\">docs</a>");
+        assert_eq!(convert("docs/"), "<a href=\"docs/index.html\">docs</a>");
+        assert_eq!(convert("nowhere"), "<a href=\"nowhere\">docs</a>");
+    }
+
+    #[test]
+    fn navbar_exclude_keeps_pages_built() {
+        let fixture = Fixture::new("navbar-exclude", &[
+            ("index.md", "home"), ("math/sir.md", "sir"), ("blog/first.md", "one"), ("blog/second.md", "two"),
+        ]);
+        let mut skipped = 0;
+        let (pages, _, _) = discover_pages(std::slice::from_ref(&fixture.0), &mut skipped).unwrap();
+        let keys: Vec<String> = pages.iter().map(|(_, rel, _)| page_key(rel)).collect();
+        assert!(keys.contains(&"blog/first".to_string()) && keys.contains(&"blog/second".to_string()));
+
+        let nav = |config: &str| {
+            let config: Config = serde_yaml::from_str(config).unwrap();
+            let items = build_navbar_items(&config, &pages, &std::collections::HashSet::new());
+            generate_navbar(&items, true, None, &HashMap::new(), Some("index"), "", None)
+        };
+        for config in [
+            "navbar_exclude: [blog/]\n",
+            "navbar_exclude: [blog/]\npage_order: [\"*\", \"*/*\"]\n",
+            "navbar_exclude: [\"blog/*\"]\nnavbar_order: [\"*/*\"]\n",
+            "navbar_exclude: [blog]\nnavbar_order: [\"**\"]\npage_order: [math/sir]\n",
+        ] {
+            let nav = nav(config);
+            assert!(nav.contains("href=\"math/sir.html\""), "{}", config);
+            assert!(!nav.contains("blog/"), "{}", config);
+        }
+
+        // Naming a page outright puts it back
+        let nav = nav("navbar_exclude: [blog/]\nnavbar_order: [\"math/*\", blog/second]\n");
+        assert!(nav.contains("href=\"blog/second.html\"") && !nav.contains("blog/first"));
+        assert!(nav.contains("href=\"math/sir.html\""));
+    }
+
+    #[test]
+    fn cache_fingerprint_invalidation() {
+        let fixture = Fixture::new("fingerprint", &[
+            ("config.yaml", "build_cache: true\n"),
+            ("assets/footer.html", "<footer></footer>"),
+            ("assets/templates/landing.html", "{{content}}"),
+            ("content/_includes/note.md", "Note"),
+        ]);
+        let root = &fixture.0;
+        let config = root.join("config.yaml");
+        let includes = [root.join("content/_includes")];
+        let pages = vec![
+            (root.join("content/index.md"), PathBuf::from("index.md"), "Home".to_string()),
+            (root.join("content/a.md"), PathBuf::from("a.md"), "A".to_string()),
+        ];
+        let fingerprint = |generator: &str, pages: &[(PathBuf, PathBuf, String)]| {
+            site_fingerprint(generator, &config, &root.join("assets"), pages, &includes)
+        };
+        let base = fingerprint("v1", &pages);
+        assert_eq!(fingerprint("v1", &pages), base);
+
+        // A different generator build, even at the same version
+        assert_ne!(fingerprint("v2", &pages), base);
+
+        // Page titles, paths and order
+        let mut renamed = pages.clone();
+        renamed[1].2 = "A, renamed".to_string();
+        assert_ne!(fingerprint("v1", &renamed), base);
+        let reordered: Vec<_> = pages.iter().rev().cloned().collect();
+        assert_ne!(fingerprint("v1", &reordered), base);
+
+        // Files every page depends on; editing and restoring one restores the fingerprint
+        for (file, edit) in [
+            ("config.yaml", "build_cache: true\ntoc: true\n"),
+            ("assets/template.html", "<main>{{content}}</main>"),
+            ("assets/footer.html", "<footer>new</footer>"),
+            ("assets/templates/landing.html", "<div>{{content}}</div>"),
+            ("assets/footers/math.html", "<footer>math</footer>"),
+            ("content/_includes/note.md", "Changed note"),
+            ("content/_includes/new.md", "New include"),
+        ] {
+            let path = root.join(file);
+            let original = fs::read(&path).ok();
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, edit).unwrap();
+            assert_ne!(fingerprint("v1", &pages), base, "{}", file);
+            match original {
+                Some(original) => fs::write(&path, original).unwrap(),
+                None => fs::remove_file(&path).unwrap(),
+            }
+            assert_eq!(fingerprint("v1", &pages), base, "{}", file);
+        }
+
+        // The real generator fingerprint is stable within a build
+        assert_eq!(generator_fingerprint(), generator_fingerprint());
+    }
+
+    #[test]
+    fn cache_entries_need_matching_fingerprint_and_output() {
+        let fixture = Fixture::new("cache", &[("dist/a.html", "<p>A</p>")]);
+        let dist = fixture.0.join("dist");
+        let cache = BuildCache {
+            fingerprint: "site-1".to_string(),
+            pages: std::collections::BTreeMap::from([("a.html".to_string(), "hash-a".to_string())]),
+        };
+        fs::write(dist.join(BUILD_CACHE_FILE), serde_json::to_string(&cache).unwrap()).unwrap();
+
+        let same_site = BuildCache::load(&dist, "site-1");
+        assert!(same_site.is_fresh("a.html", "hash-a", &dist.join("a.html")));
+        assert!(!same_site.is_fresh("a.html", "hash-b", &dist.join("a.html")));
+        assert!(!same_site.is_fresh("a.html", "hash-a", &dist.join("missing.html")));
+
+        let changed_site = BuildCache::load(&dist, "site-2");
+        assert!(changed_site.pages.is_empty());
+        assert_eq!(changed_site.fingerprint, "site-2");
+    }
+
+    #[test]
+    fn tex_whitespace_normalization() {
+        assert_eq!(normalize_tex("  x + y  ", false), "x + y");
+        assert_eq!(normalize_tex(" \n x \n ", false), "x");
+        let aligned = "\\begin{aligned}\n  a &= b \\\\\n\n  c &= d\n\\end{aligned}";
+        assert_eq!(normalize_tex(aligned, false), "\\begin{aligned} a &= b \\\\ c &= d \\end{aligned}");
+        assert_eq!(normalize_tex(aligned, true), "\\begin{aligned}\na &= b \\\\\nc &= d\n\\end{aligned}");
+        // A comment needs its line ending, so lines stay separate
+        assert_eq!(normalize_tex("x % note\n+ y", false), "x % note\n+ y");
+        assert_eq!(normalize_tex("50\\% + y", false), "50\\% + y");
+    }
+
+    #[test]
+    fn module_script_tag() {
+        let frontmatter: FrontMatter = serde_yaml::from_str(
+            "scripts:\n  - {src: js/app.js, module: true, defer: true}\n  - js/plain.js\n  - https://cdn.example.com/lib.js\n"
+        ).unwrap();
+        let tags: Vec<String> = frontmatter.scripts.iter().flatten().map(|s| s.to_tag("../")).collect();
+        assert_eq!(tags, [
+            "<script type=\"module\" defer src=\"../js/app.js\"></script>",
+            "<script src=\"../js/plain.js\"></script>",
+            "<script src=\"https://cdn.example.com/lib.js\"></script>",
+        ]);
+    }
+
+    #[test]
+    fn colocated_files_skip_hidden_and_ignored() {
+        let fixture = Fixture::new("colocated", &[
+            ("post/index.md", "![Chart](chart.png)"),
+            ("post/chart.png", "png"),
+            ("post/files/notes.pdf", "pdf"),
+            ("post/.DS_Store", "junk"),
+            ("post/scratch.psd", "psd"),
+            (".ideepignore", "drafts/"),
+            (".obsidian/workspace.json", "{}"),
+            ("drafts/idea.png", "png"),
+            ("_includes/snippet.png", "png"),
+        ]);
+        let root = &fixture.0;
+        let ignore = IgnoreRules::parse("drafts/\n*.psd\n");
+        let (mut pages, mut assets, mut skipped, mut weights) = (Vec::new(), Vec::new(), 0, HashMap::new());
+        find_markdown_files(root, root, &mut pages, &mut assets, &mut skipped, &ignore, &mut weights).unwrap();
+        let assets: Vec<String> = assets.iter().map(|(_, rel)| rel.to_string_lossy().replace('\\', "/")).collect();
+        assert_eq!(assets, ["post/chart.png", "post/files/notes.pdf"]);
+        assert_eq!(pages.len(), 1);
+    }
+
+    #[test]
+    fn colocated_references_resolve_from_any_page() {
+        let colocated: std::collections::HashSet<String> = ["post/chart.png", "post/files/notes.pdf", "shared/logo.svg"]
+            .iter().map(|s| s.to_string()).collect();
+        let html = render("![Chart](chart.png)\n\n[Notes](files/notes.pdf#page=2) [Logo](../shared/logo.svg) \
+                           [Missing](other.png) [Web](https://example.com/chart.png)\n", &Config::default());
+        let rewritten = rewrite_colocated_refs(&html, Path::new("post"), &colocated, "../");
+        assert!(rewritten.contains("<img src=\"../post/chart.png\" alt=\"Chart\" />"));
+        assert!(rewritten.contains("href=\"../post/files/notes.pdf#page=2\""));
+        assert!(rewritten.contains("href=\"../shared/logo.svg\""));
+        assert!(rewritten.contains("href=\"other.png\""));
+        assert!(rewritten.contains("href=\"https://example.com/chart.png\""));
+
+        // The 404 page and base_path deployments use a root-absolute prefix instead
+        let rewritten = rewrite_colocated_refs(&html, Path::new("post"), &colocated, "/course/");
+        assert!(rewritten.contains("<img src=\"/course/post/chart.png\""));
+    }
+
+    #[test]
+    fn config_formats_are_equivalent() {
+        let yaml = "site_url: https://example.com\nsearch_index: true\npage_order:\n  - intro\n  - tutorials/*\nmarkdown:\n  tables: false\n";
+        let toml = "site_url = \"https://example.com\"\nsearch_index = true\npage_order = [\"intro\", \"tutorials/*\"]\n\n[markdown]\ntables = false\n";
+        let json = r#"{"site_url": "https://example.com", "search_index": true, "page_order": ["intro", "tutorials/*"], "markdown": {"tables": false}}"#;
+        for (content, file) in [(yaml, "config.yaml"), (toml, "config.toml"), (json, "config.json")] {
+            let config = parse_config(content, Path::new(file)).unwrap();
+            assert_eq!(config.site_url.as_deref(), Some("https://example.com"), "{}", file);
+            assert_eq!(config.search_index, Some(true), "{}", file);
+            let order: Vec<&str> = config.page_order.iter().flatten().filter_map(|v| v.as_str()).collect();
+            assert_eq!(order, ["intro", "tutorials/*"], "{}", file);
+            assert_eq!(config.markdown.tables, Some(false), "{}", file);
+            assert!(config.unknown.is_empty(), "{}", file);
+        }
+        assert!(parse_config("site_url = ", Path::new("config.toml")).is_err());
+    }
+
+    #[test]
+    fn relative_and_absolute_links() {
+        let files = links(&[("index", "index"), ("math/sir", "math/sir"), ("prog/b", "prog/b"), ("other", "other")]);
+        let relative: Config = serde_yaml::from_str("site_url: https://example.com/course\n").unwrap();
+        let absolute: Config = serde_yaml::from_str("site_url: https://example.com/course\nabsolute_links: true\n").unwrap();
+        assert_eq!(link_base_path(&relative), None);
+        assert_eq!(link_base_path(&absolute).as_deref(), Some("https://example.com/course/"));
+
+        let page = "<a href=\"../prog/b.md\">b</a> <a href=\"../other.md#top\">o</a> <a href=\"index\">i</a> <a href=\"https://example.org\">e</a>";
+        let convert = |config: &Config| {
+            convert_internal_links(page, &files, &link_base_path(config).unwrap_or_default(), Path::new("math/sir.md"))
+        };
+        assert_eq!(
+            convert(&relative),
+            "<a href=\"../prog/b.html\">b</a> <a href=\"../other.html#top\">o</a> <a href=\"../index.html\">i</a> <a href=\"https://example.org\">e</a>"
+        );
+        assert_eq!(
+            convert(&absolute),
+            "<a href=\"https://example.com/course/prog/b.html\">b</a> <a href=\"https://example.com/course/other.html#top\">o</a> \
+             <a href=\"https://example.com/course/index.html\">i</a> <a href=\"https://example.org\">e</a>"
+        );
+
+        // The navbar takes the same prefix as page assets
+        let items = [NavbarItem::MarkdownFile(PathBuf::from("prog/b.md"), "B".to_string())];
+        let navbar = |prefix: &str| generate_navbar(&items, true, None, &HashMap::new(), Some("math/sir"), prefix, None);
+        assert!(navbar("../").contains("href=\"../prog/b.html\""));
+        assert!(navbar("https://example.com/course/").contains("href=\"https://example.com/course/prog/b.html\""));
+        assert!(!navbar("https://example.com/course/").contains("href=\"../"));
+    }
+
+    #[test]
+    fn slugs() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  Leading and trailing  "), "leading-and-trailing");
+        assert_eq!(slugify("...Punctuation first!?"), "punctuation-first");
+        assert_eq!(slugify("Many   spaces -- and - dashes"), "many-spaces-and-dashes");
+        assert_eq!(slugify("-Edge-"), "edge");
+        assert_eq!(slugify("Ünïcödé Straße"), "ünïcödé-straße");
+        assert_eq!(slugify("日本語 見出し"), "日本語-見出し");
+        assert_eq!(slugify("SIR Model (2024)"), "sir-model-2024");
+        assert_eq!(slugify("!!!"), "section");
+        assert_eq!(slugify(""), "section");
+    }
+
+    #[test]
+    fn heading_ids_are_unique() {
+        let html = add_heading_ids("<h2>Results</h2>\n<h2>Results</h2>\n<h3 id=\"mine\">Kept</h3>\n<h2>Results</h2>\n");
+        assert_eq!(html, "<h2 id=\"results\">Results</h2>\n<h2 id=\"results-1\">Results</h2>\n<h3 id=\"mine\">Kept</h3>\n<h2 id=\"results-2\">Results</h2>\n");
+    }
+
+    #[test]
+    fn data_template_loops() {
+        let data: serde_yaml::Value = serde_yaml::from_str(
+            "course: Bio 301\nteams:\n  - name: Red\n    members: [Ann, Bo]\n  - name: <Blue>\n    members: [Cy]\n"
+        ).unwrap();
+        let markdown = "# {{ data.course }}\n{% for team in data.teams %}## {{ team.name }}\n{% for m in team.members %}- {{ m }}\n{% endfor %}{% endfor %}";
+        assert_eq!(
+            expand_data_template(markdown, &data, Path::new("page.md")),
+            "# Bio 301\n## Red\n- Ann\n- Bo\n## &lt;Blue&gt;\n- Cy\n"
+        );
+        assert_eq!(expand_data_template("{{ data.teams.1.members.0 }}", &data, Path::new("page.md")), "Cy");
+    }
+
+    #[test]
+    fn toc_levels() {
+        let headings: String = (1..=6).map(|level| format!("<h{0}>Level {0}</h{0}>\n", level)).collect();
+        let html = add_heading_ids(&headings);
+        // Every heading gets an id, whether or not it's listed
+        for level in 1..=6 {
+            assert!(html.contains(&format!("<h{0} id=\"level-{0}\">", level)));
+        }
+        let toc = render_toc(&html, 2, 4);
+        for level in 1..=6 {
+            let item = format!("<li class=\"toc-level-{0}\"><a href=\"#level-{0}\">Level {0}</a></li>", level);
+            assert_eq!(toc.contains(&item), (2..=4).contains(&level), "level {}", level);
+        }
+        assert_eq!(render_toc(&html, 1, 3).matches("<li").count(), 3);
+        assert_eq!(render_toc(&html, 6, 6).matches("<li").count(), 1);
+        assert_eq!(render_toc("<p>No headings</p>", 1, 6), "");
+
+        let config: Config = serde_yaml::from_str("toc: true\ntoc_min: 2\ntoc_depth: 2\n").unwrap();
+        let page = render("# Title\n\n## Section\n\n### Detail\n", &config);
+        assert!(page.starts_with("<nav class=\"toc\""));
+        assert!(page.contains("<a href=\"#section\">Section</a>"));
+        assert!(!page.contains("<a href=\"#title\">") && !page.contains("<a href=\"#detail\">"));
+    }
+
+    #[test]
+    fn precompression_round_trips() {
+        use std::io::Read;
+        assert_eq!(Precompression::from_config(" gz "), Some(Precompression::Gzip));
+        assert_eq!(Precompression::from_config("br"), Some(Precompression::Brotli));
+        assert_eq!(Precompression::from_config("zstd"), None);
+
+        let page = "<!DOCTYPE html><html><body><p>Hello</p></body></html>\n".repeat(50);
+        let gzipped = Precompression::Gzip.compress(page.as_bytes()).unwrap();
+        assert_eq!(gzipped, Precompression::Gzip.compress(page.as_bytes()).unwrap());
+        let mut unzipped = String::new();
+        flate2::read::GzDecoder::new(&gzipped[..]).read_to_string(&mut unzipped).unwrap();
+        assert_eq!(unzipped, page);
+
+        let brotlied = Precompression::Brotli.compress(page.as_bytes()).unwrap();
+        let mut unbrotlied = String::new();
+        brotli::Decompressor::new(&brotlied[..], 4096).read_to_string(&mut unbrotlied).unwrap();
+        assert_eq!(unbrotlied, page);
+        assert!(gzipped.len() < page.len() && brotlied.len() < page.len());
+    }
+
+    #[test]
+    fn drafts_are_skipped() {
+        let fixture = Fixture::new("drafts", &[
+            ("config.yaml", "title_heading: true\n"),
+            ("content/index.md", "---\ntitle: Home\n---\n"),
+            ("content/published.md", "---\ntitle: Out\ndraft: false\n---\n"),
+        ]);
+        let options = BuildOptions {
+            config_path: Some(fixture.0.join("config.yaml")),
+            content_dirs: vec![fixture.0.join("content")],
+            ..Default::default()
+        };
+        new_page(&options, "math/new-topic").unwrap();
+        let stub = fs::read_to_string(fixture.0.join("content/math/new-topic.md")).unwrap();
+        let frontmatter = extract_frontmatter(&stub).0.unwrap();
+        assert_eq!(frontmatter.title.as_deref(), Some("New topic"));
+        assert_eq!(frontmatter.draft, Some(true));
+        assert!(new_page(&options, "math/new-topic").is_err());
+        assert!(new_page(&options, "../outside").is_err());
+
+        let mut skipped = 0;
+        let (pages, _, _) = discover_pages(&options.content_dirs, &mut skipped).unwrap();
+        let keys: Vec<String> = pages.iter().map(|(_, rel, _)| page_key(rel)).collect();
+        assert_eq!(keys, ["index", "published"]);
+        assert_eq!(skipped, 1);
+    }
+}